@@ -1,33 +1,97 @@
+use serde::{Serialize, Deserialize};
+
 fn main() {
-    let record_str = std::fs::read_to_string("day02/data/input.txt").expect("failed to read file");
+    let args = std::env::args().collect::<Vec<String>>();
+    let arg = |flag: &str| args.iter().position(|arg| arg == flag).and_then(|i| args.get(i + 1)).map(String::as_str);
+
+    // the record is read as json or csv if the input file has that extension, otherwise as text
+    let path = arg("--input").unwrap_or("day02/data/input.txt");
+    let record_str = std::fs::read_to_string(path).expect("failed to read file");
     let expectation = CubeSet{red: 12, green: 13, blue: 14};
 
-    let record = read_record(&record_str, expectation).expect("failed to read record");
+    let record = if path.ends_with(".json") {
+        record_from_json(&record_str).expect("failed to import json")
+    } else if path.ends_with(".csv") {
+        record_from_csv(&record_str, expectation).expect("failed to import csv")
+    } else {
+        read_record(&record_str, expectation).expect("failed to read record")
+    };
     let sum = record.calc_sum();
     println!("sum: {sum}");
 
     let sum_power_max = record.sum_power_max();
     println!("sum_power_max: {sum_power_max}");
+
+    let allowed = record.allowed_games(&record.expectation);
+    println!("allowed games: {allowed:?}");
+
+    let min_bag = record.min_bag();
+    println!("min_bag: {min_bag:?}");
+
+    let min_bag_allowed = record.min_bag_for(&allowed);
+    println!("min_bag for allowed games: {min_bag_allowed:?}");
+
+    for (id, violations) in record.violations(&record.expectation) {
+        for v in violations {
+            println!("game {id}: set {} has {} {:?} (limit {})", v.set, v.count, v.color, v.limit);
+        }
+    }
+
+    // the export format is chosen by extension in the same way
+    if let Some(path) = arg("--export") {
+        let exported = if path.ends_with(".json") {
+            record_to_json(&record).expect("failed to export json")
+        } else if path.ends_with(".csv") {
+            record_to_csv(&record).expect("failed to export csv")
+        } else {
+            write_record(&record)
+        };
+        std::fs::write(path, exported).expect("failed to write export");
+    }
+
+    let bags = [CubeSet{red: 12, green: 13, blue: 14}, min_bag_allowed, min_bag];
+    let admitted = record.admitted_counts(&bags);
+    for (bag, count) in bags.iter().zip(admitted) {
+        println!("{bag:?} admits {count} games");
+    }
 }
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Color {
+    Red,
+    Green,
+    Blue
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 struct CubeSet {
     red: u32,
     green: u32,
     blue: u32
 }
 
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 struct Game {
     id: u32,
     sets: Vec<CubeSet>
 }
 
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 struct Record {
     expectation: CubeSet,
     games: Vec<Game>
 }
 
+// a single set of a game which drew more cubes of one color than the bag holds
+#[derive(Debug, PartialEq, Eq)]
+struct Violation {
+    set: usize,   // index of the set within the game
+    color: Color, // offending color
+    count: u32,   // cubes drawn in that set
+    limit: u32    // cubes of that color in the bag
+}
+
 impl Game {
     fn check_game(&self, expectation: &CubeSet) -> bool {
         self.sets.iter().all(|set| set.subset(expectation))
@@ -44,6 +108,20 @@ impl Game {
 
         return max;
     }
+
+    fn violations(&self, bag: &CubeSet) -> Vec<Violation> {
+        let mut violations = vec![];
+
+        for (index, set) in self.sets.iter().enumerate() {
+            for color in [Color::Red, Color::Green, Color::Blue] {
+                if set.get(color) > bag.get(color) {
+                    violations.push(Violation{set: index, color, count: set.get(color), limit: bag.get(color)});
+                }
+            }
+        }
+
+        violations
+    }
 }
 
 impl Record {
@@ -68,9 +146,63 @@ impl Record {
 
         return sum;
     }
+
+    // ids of all games which are possible with the given bag
+    fn allowed_games(&self, bag: &CubeSet) -> Vec<u32> {
+        self.games.iter().filter(|game| game.check_game(bag)).map(|game| game.id).collect()
+    }
+
+    // smallest bag with which every game is possible
+    fn min_bag(&self) -> CubeSet {
+        self.games.iter().fold(CubeSet{red: 0, green: 0, blue: 0}, |acc, game| acc.max(&game.calc_max()))
+    }
+
+    // smallest bag with which every game in ids is possible, unknown ids are ignored
+    fn min_bag_for(&self, ids: &[u32]) -> CubeSet {
+        self.games.iter()
+            .filter(|game| ids.contains(&game.id))
+            .fold(CubeSet{red: 0, green: 0, blue: 0}, |acc, game| acc.max(&game.calc_max()))
+    }
+
+    // every impossible game with the sets and colors which break it
+    fn violations(&self, bag: &CubeSet) -> Vec<(u32, Vec<Violation>)> {
+        let mut result = vec![];
+
+        for game in &self.games {
+            let violations = game.violations(bag);
+            if !violations.is_empty() {
+                result.push((game.id, violations));
+            }
+        }
+
+        result
+    }
+
+    // number of possible games for each of the candidate bags
+    fn admitted_counts(&self, bags: &[CubeSet]) -> Vec<usize> {
+        let maxima = self.games.iter().map(|game| game.calc_max()).collect::<Vec<CubeSet>>();
+
+        bags.iter().map(|bag| maxima.iter().filter(|max| max.subset(bag)).count()).collect()
+    }
 }
 
 impl CubeSet {
+    fn get(&self, color: Color) -> u32 {
+        match color {
+            Color::Red => self.red,
+            Color::Green => self.green,
+            Color::Blue => self.blue,
+        }
+    }
+
+    fn max(&self, other: &CubeSet) -> CubeSet {
+        CubeSet{
+            red: self.red.max(other.red),
+            green: self.green.max(other.green),
+            blue: self.blue.max(other.blue),
+        }
+    }
+
     fn subset(&self, other: &CubeSet) -> bool {
         return self.red <= other.red && self.green <= other.green && self.blue <= other.blue;
     }
@@ -174,4 +306,203 @@ fn read_record(record_str: &str, expectation: CubeSet) -> Option<Record> {
     }
 
     return Some(record);
-}
\ No newline at end of file
+}
+
+fn write_set(set: &CubeSet) -> String {
+    let mut colors = vec![];
+
+    for (count, name) in [(set.red, "red"), (set.green, "green"), (set.blue, "blue")] {
+        if count > 0 {
+            colors.push(format!("{count} {name}"));
+        }
+    }
+
+    // an empty set has to name at least one color to be readable again
+    if colors.is_empty() {
+        colors.push("0 red".to_string());
+    }
+
+    colors.join(", ")
+}
+
+fn write_game(game: &Game) -> String {
+    let sets = game.sets.iter().map(write_set).collect::<Vec<String>>();
+    format!("Game {}: {}", game.id, sets.join("; "))
+}
+
+// canonical text form of the record, colors are always written as red, green, blue
+fn write_record(record: &Record) -> String {
+    let mut record_str = String::new();
+
+    for game in &record.games {
+        record_str.push_str(&write_game(game));
+        record_str.push('\n');
+    }
+
+    record_str
+}
+
+fn record_to_json(record: &Record) -> Result<String, serde_json::Error> {
+    serde_json::to_string_pretty(record)
+}
+
+// every game has at least one set, as in the text and csv forms, which can not express empty games
+fn record_from_json(json: &str) -> Result<Record, serde_json::Error> {
+    let record: Record = serde_json::from_str(json)?;
+
+    if let Some(game) = record.games.iter().find(|game| game.sets.is_empty()) {
+        return Err(serde::de::Error::custom(format!("game {} has no sets", game.id)));
+    }
+
+    Ok(record)
+}
+
+// one csv row per set, the sets of a game are in consecutive rows
+#[derive(Debug, Serialize, Deserialize)]
+struct SetRow {
+    game: u32,
+    red: u32,
+    green: u32,
+    blue: u32
+}
+
+fn record_to_csv(record: &Record) -> Result<String, csv::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    for game in &record.games {
+        for set in &game.sets {
+            writer.serialize(SetRow{game: game.id, red: set.red, green: set.green, blue: set.blue})?;
+        }
+    }
+
+    let bytes = writer.into_inner().map_err(|e| e.into_error())?;
+    Ok(String::from_utf8(bytes).expect("csv writer produced invalid utf8"))
+}
+
+fn record_from_csv(csv_str: &str, expectation: CubeSet) -> Result<Record, csv::Error> {
+    let mut record = Record {expectation, games: vec![]};
+    let mut reader = csv::Reader::from_reader(csv_str.as_bytes());
+
+    for row in reader.deserialize() {
+        let row: SetRow = row?;
+        let set = CubeSet{red: row.red, green: row.green, blue: row.blue};
+
+        match record.games.last_mut() {
+            Some(game) if game.id == row.game => game.sets.push(set),
+            _ => record.games.push(Game{id: row.game, sets: vec![set]}),
+        }
+    }
+
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green
+Game 2: 1 blue, 2 green; 3 green, 4 blue, 1 red; 1 green, 1 blue
+Game 3: 8 green, 6 blue, 20 red; 5 blue, 4 red, 13 green; 5 green, 1 red
+Game 4: 1 green, 3 red, 6 blue; 3 green, 6 red; 3 green, 15 blue, 14 red
+Game 5: 6 red, 1 blue, 3 green; 2 blue, 1 red, 2 green
+";
+
+    fn sample() -> Record {
+        read_record(SAMPLE, CubeSet{red: 12, green: 13, blue: 14}).expect("failed to read sample")
+    }
+
+    #[test]
+    fn puzzle_answers_on_sample() {
+        let record = sample();
+        assert_eq!(record.calc_sum(), 8);
+        assert_eq!(record.sum_power_max(), 2286);
+    }
+
+    #[test]
+    fn allowed_games_on_sample() {
+        let record = sample();
+        assert_eq!(record.allowed_games(&record.expectation), vec![1, 2, 5]);
+        assert_eq!(record.allowed_games(&CubeSet{red: 0, green: 0, blue: 0}), Vec::<u32>::new());
+        assert_eq!(record.allowed_games(&record.min_bag()), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn min_bags_on_sample() {
+        let record = sample();
+        assert_eq!(record.min_bag(), CubeSet{red: 20, green: 13, blue: 15});
+        assert_eq!(record.min_bag_for(&[1, 2, 5]), CubeSet{red: 6, green: 3, blue: 6});
+        assert_eq!(record.min_bag_for(&[1, 99]), CubeSet{red: 4, green: 2, blue: 6});
+        assert_eq!(record.min_bag_for(&[]), CubeSet{red: 0, green: 0, blue: 0});
+    }
+
+    #[test]
+    fn violations_on_sample() {
+        let record = sample();
+        assert_eq!(record.violations(&record.expectation), vec![
+            (3, vec![Violation{set: 0, color: Color::Red, count: 20, limit: 12}]),
+            (4, vec![
+                Violation{set: 2, color: Color::Red, count: 14, limit: 12},
+                Violation{set: 2, color: Color::Blue, count: 15, limit: 14}
+            ])
+        ]);
+        assert!(record.violations(&record.min_bag()).is_empty());
+    }
+
+    #[test]
+    fn admitted_counts_on_sample() {
+        let record = sample();
+        let bags = [record.expectation, CubeSet{red: 6, green: 3, blue: 6}, record.min_bag(), CubeSet{red: 0, green: 0, blue: 0}];
+        assert_eq!(record.admitted_counts(&bags), vec![3, 3, 5, 0]);
+        assert_eq!(record.admitted_counts(&[]), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let record = sample();
+        let written = write_record(&record);
+        assert_eq!(read_record(&written, record.expectation), Some(record));
+    }
+
+    #[test]
+    fn text_is_canonical() {
+        let written = write_record(&sample());
+        assert!(written.starts_with("Game 1: 4 red, 3 blue; 1 red, 2 green, 6 blue; 2 green\n"));
+        assert_eq!(write_record(&read_record(&written, sample().expectation).unwrap()), written);
+    }
+
+    #[test]
+    fn empty_set_round_trip() {
+        let record = Record {
+            expectation: CubeSet{red: 1, green: 2, blue: 3},
+            games: vec![Game{id: 7, sets: vec![CubeSet{red: 0, green: 0, blue: 0}, CubeSet{red: 0, green: 5, blue: 0}]}]
+        };
+        assert_eq!(write_record(&record), "Game 7: 0 red; 5 green\n");
+        assert_eq!(read_record(&write_record(&record), record.expectation), Some(record));
+
+        // a game without any set can not be written as text or csv, so it is not imported at all
+        let json = "{\"expectation\": {\"red\": 1, \"green\": 2, \"blue\": 3}, \"games\": [{\"id\": 7, \"sets\": []}]}";
+        assert_eq!(record_from_json(json).unwrap_err().to_string(), "game 7 has no sets");
+        assert_eq!(read_record("Game 7: \n", CubeSet{red: 1, green: 2, blue: 3}), None);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let record = sample();
+        let json = record_to_json(&record).unwrap();
+        assert_eq!(record_from_json(&json).unwrap(), record);
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let record = sample();
+        let csv = record_to_csv(&record).unwrap();
+        assert!(csv.starts_with("game,red,green,blue\n1,4,0,3\n"));
+        assert_eq!(record_from_csv(&csv, record.expectation).unwrap(), record);
+    }
+
+    #[test]
+    fn invalid_input_is_rejected() {
+        assert!(record_from_json("{\"games\": []}").is_err());
+        assert!(record_from_csv("game,red,green,blue\n1,x,0,0\n", sample().expectation).is_err());
+    }
+}
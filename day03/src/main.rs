@@ -1,91 +1,526 @@
 fn main() {
     let input = std::fs::read_to_string("day03/data/input.txt").expect("failed to read file");
-    let mut numbers: Vec<GridNumber> = vec![];
-    let mut row = 0;
-    let mut column: i32;
-    let mut number_buf = String::new();
-
-    for line in input.lines() {
-        let mut column = 0;
-        for char in line.chars() {
-            if char.is_digit(10) {
-                number_buf.push(char);
-            } else if !number_buf.is_empty() {
-                let number = number_buf.parse::<u32>().expect("failed to parse number");
-                let length = number_buf.len() as u32;
-                numbers.push(GridNumber { number: number, l: row, x: column-length, y: column-1, marked: false});
-                number_buf.clear();
+    let schematic = Schematic::parse(&input);
+
+    // optional rendering of the schematic for debugging inputs
+    match std::env::args().nth(1).as_deref() {
+        Some("ansi") => print!("{}", schematic.render_ansi(&GearRule::new())),
+        Some("html") => print!("{}", schematic.render_html(&GearRule::new())),
+        _ => {}
+    }
+
+    let sum: u32 = schematic.part_numbers().iter().fold(0, |acc, n| acc + n.number);
+
+    let gear_ratio_sum = schematic.gear_ratio_sum(&GearRule::new()).expect("gear ratios overflowed");
+    let largest_gear = schematic.gears(&GearRule::new()).into_iter().max_by_key(|gear| gear.ratio);
+
+    // same schematic, different questions
+    let any_symbol_max = schematic.gear_ratio_sum(&GearRule{
+        symbols: SymbolClass::Custom(|c| c.is_ascii_punctuation()),
+        count: Count::AtLeast(1),
+        reducer: Reducer::Max,
+        ..GearRule::new()
+    });
+    let orthogonal_sum = schematic.gear_ratio_sum(&GearRule{
+        symbols: SymbolClass::Any,
+        count: Count::AtLeast(2),
+        reducer: Reducer::Sum,
+        adjacency: Adjacency::Orthogonal
+    });
+    let custom = schematic.gear_ratio_sum(&GearRule{
+        reducer: Reducer::Custom(|numbers| Some(numbers.iter().map(|&n| n as u64).min().unwrap_or(0))),
+        ..GearRule::new()
+    });
+
+    let show = |sum: Option<u64>| sum.map_or("overflow".to_string(), |sum| sum.to_string());
+    let unmarked = (0..schematic.numbers.len()).filter(|&n| schematic.symbols_adjacent_to_number(n).is_empty()).count();
+
+    println!("sum : {sum}");
+    println!("gear_ratio_sum : {gear_ratio_sum}");
+    println!("numbers without symbol : {unmarked}");
+    if let Some(gear) = largest_gear {
+        let symbol = schematic.symbols[gear.symbol];
+        let numbers = gear.numbers.iter().map(|&n| schematic.numbers[n].number).collect::<Vec<u32>>();
+        println!("largest gear : '{}' at ({}, {}) with {numbers:?} -> {}", symbol.char, symbol.row, symbol.column, show(gear.ratio));
+    }
+    println!("sum of largest numbers around symbols : {}", show(any_symbol_max));
+    println!("orthogonal sum around any symbol : {}", show(orthogonal_sum));
+    println!("sum of smaller gear numbers : {}", show(custom));
+
+}
+
+#[derive(Clone, Copy, Debug)]
+struct GridNumber {
+    number: u32,  // value of the parsed string
+    l: usize,     // height/line in the grid
+    x: usize,     // leftmost position
+    y: usize,     // rightmost position
+    marked: bool  // marked or not
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Symbol {
+    char: char,
+    row: usize,
+    column: usize
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Cell {
+    Empty,
+    Number(usize), // index into Schematic::numbers
+    Symbol(usize)  // index into Schematic::symbols
+}
+
+// which neighbours of a cell count as adjacent
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Adjacency {
+    Orthogonal, // 4-neighbourhood
+    Diagonal    // 8-neighbourhood
+}
+
+// which symbols can be gears
+#[derive(Clone, Debug)]
+enum SymbolClass {
+    Any,
+    OneOf(Vec<char>),
+    Custom(fn(char) -> bool)
+}
+
+// how many adjacent numbers a gear needs
+#[derive(Clone, Copy, Debug)]
+enum Count {
+    Exactly(usize),
+    AtLeast(usize)
+}
+
+// how the adjacent numbers of a gear are combined into its ratio, None signals an overflow
+#[derive(Clone, Copy, Debug)]
+enum Reducer {
+    Product,
+    Sum,
+    Max,
+    Custom(fn(&[u32]) -> Option<u64>)
+}
+
+#[derive(Clone, Debug)]
+struct GearRule {
+    symbols: SymbolClass,
+    count: Count,
+    reducer: Reducer,
+    adjacency: Adjacency
+}
+
+#[derive(Clone, Debug)]
+struct Gear {
+    symbol: usize,       // index into Schematic::symbols
+    numbers: Vec<usize>, // indices into Schematic::numbers
+    ratio: Option<u64>   // None if the reducer overflowed
+}
+
+impl SymbolClass {
+    fn contains(&self, char: char) -> bool {
+        match self {
+            SymbolClass::Any => true,
+            SymbolClass::OneOf(chars) => chars.contains(&char),
+            SymbolClass::Custom(f) => f(char),
+        }
+    }
+}
+
+impl Count {
+    fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Count::Exactly(n) => count == n,
+            Count::AtLeast(n) => count >= n,
+        }
+    }
+}
+
+impl Reducer {
+    fn reduce(&self, numbers: &[u32]) -> Option<u64> {
+        match self {
+            Reducer::Product => numbers.iter().try_fold(1u64, |acc, &n| acc.checked_mul(n as u64)),
+            Reducer::Sum => numbers.iter().try_fold(0u64, |acc, &n| acc.checked_add(n as u64)),
+            Reducer::Max => Some(numbers.iter().map(|&n| n as u64).max().unwrap_or(0)),
+            Reducer::Custom(f) => f(numbers),
+        }
+    }
+}
+
+impl GearRule {
+    // the puzzle's rule: a '*' next to exactly two numbers, ratio is their product
+    fn new() -> GearRule {
+        GearRule {
+            symbols: SymbolClass::OneOf(vec!['*']),
+            count: Count::Exactly(2),
+            reducer: Reducer::Product,
+            adjacency: Adjacency::Diagonal
+        }
+    }
+}
+
+// what a cell is shown as when rendering
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Highlight {
+    Empty,
+    PartNumber,    // number next to a symbol
+    NonPartNumber, // number without any symbol around it
+    Gear,          // symbol satisfying the gear rule
+    Symbol,        // any other symbol with numbers around it
+    OrphanSymbol   // symbol without any number around it
+}
+
+impl Highlight {
+    fn ansi(&self) -> &'static str {
+        match self {
+            Highlight::Empty => "\x1b[2m",
+            Highlight::PartNumber => "\x1b[32m",
+            Highlight::NonPartNumber => "\x1b[31m",
+            Highlight::Gear => "\x1b[1;33m",
+            Highlight::Symbol => "\x1b[36m",
+            Highlight::OrphanSymbol => "\x1b[35m",
+        }
+    }
+
+    fn class(&self) -> &'static str {
+        match self {
+            Highlight::Empty => "empty",
+            Highlight::PartNumber => "part",
+            Highlight::NonPartNumber => "nonpart",
+            Highlight::Gear => "gear",
+            Highlight::Symbol => "symbol",
+            Highlight::OrphanSymbol => "orphan",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Schematic {
+    numbers: Vec<GridNumber>,
+    symbols: Vec<Symbol>,
+    cells: Vec<Vec<Cell>>, // every digit of a number points to the same number
+    chars: Vec<Vec<char>>  // the input as it was read
+}
+
+impl Schematic {
+    fn parse(input: &str) -> Schematic {
+        let mut schematic = Schematic{numbers: vec![], symbols: vec![], cells: vec![], chars: vec![]};
+
+        for (row, line) in input.lines().enumerate() {
+            let mut cells = vec![];
+            let mut number_buf = String::new();
+
+            for (column, char) in line.chars().enumerate() {
+                if char.is_ascii_digit() {
+                    number_buf.push(char);
+                    cells.push(Cell::Number(schematic.numbers.len()));
+                    continue;
+                }
+
+                schematic.push_number(&mut number_buf, row, column);
+
+                if char == '.' {
+                    cells.push(Cell::Empty);
+                } else {
+                    cells.push(Cell::Symbol(schematic.symbols.len()));
+                    schematic.symbols.push(Symbol{char, row, column});
+                }
             }
 
-            column += 1;
+            schematic.push_number(&mut number_buf, row, cells.len());
+            schematic.cells.push(cells);
+            schematic.chars.push(line.chars().collect());
         }
 
-        if !number_buf.is_empty() {
-            let number = number_buf.parse::<u32>().expect("failed to parse number");
-            let length = number_buf.len() as u32;
-            numbers.push(GridNumber { number: number, l: row, x: column-length, y: column-1, marked: false});
-            number_buf.clear();
+        for s in 0..schematic.symbols.len() {
+            let symbol = schematic.symbols[s];
+            for n in schematic.numbers_adjacent_to(symbol.row, symbol.column) {
+                schematic.numbers[n].marked = true;
+            }
         }
 
-        row += 1;
+        schematic
     }
 
-    let mut gear_ratio_sum = 0;
-    let mut index = 0;
-    row = 0;
-    for line in input.lines() {
-        column = 0;
-        for char in line.chars() {
-            if !char.is_digit(10) && (char != '.') {
-                let mut match_count = 0;
-                let mut gear_ratio = 1;
+    // finishes the number in the buffer, end is the column right after its last digit
+    fn push_number(&mut self, number_buf: &mut String, row: usize, end: usize) {
+        if number_buf.is_empty() {
+            return;
+        }
 
-                for i in index..numbers.len() {
-                    let num = numbers[i];
-                    if num.l > row + 1 {
-                        continue;
-                    }
-                    if num.l < row - 1 {
-                        index = i + 1;
-                        continue;
-                    }
+        let number = number_buf.parse::<u32>().expect("failed to parse number");
+        self.numbers.push(GridNumber{number, l: row, x: end - number_buf.len(), y: end - 1, marked: false});
+        number_buf.clear();
+    }
 
-                    if row - 1 <= num.l && num.l <= row + 1 {
-                        for offset in -1..=1 {
-                            let test = ((column as i32) + offset) as u32;
-                            if num.x <= test && test <= num.y {
-                                numbers[i].marked = true;
-                                match_count += 1;
-                                gear_ratio *= num.number;
-                                break;
-                            }
-                        }
+    fn cell(&self, row: usize, column: usize) -> Cell {
+        self.cells.get(row).and_then(|cells| cells.get(column)).copied().unwrap_or(Cell::Empty)
+    }
+
+    // all cells around the given one which lie inside the grid
+    fn neighbours(&self, row: usize, column: usize, adjacency: Adjacency) -> Vec<(usize, usize)> {
+        let mut neighbours = vec![];
+
+        for r in row.saturating_sub(1)..=row + 1 {
+            for c in column.saturating_sub(1)..=column + 1 {
+                if adjacency == Adjacency::Orthogonal && r != row && c != column {
+                    continue;
+                }
+
+                if (r, c) != (row, column) && r < self.cells.len() && c < self.cells[r].len() {
+                    neighbours.push((r, c));
+                }
+            }
+        }
+
+        neighbours
+    }
+
+    // indices of the numbers touching the cell, each number only once
+    fn numbers_adjacent_to(&self, row: usize, column: usize) -> Vec<usize> {
+        self.numbers_adjacent_with(row, column, Adjacency::Diagonal)
+    }
+
+    fn numbers_adjacent_with(&self, row: usize, column: usize, adjacency: Adjacency) -> Vec<usize> {
+        let mut numbers = vec![];
+
+        for (r, c) in self.neighbours(row, column, adjacency) {
+            if let Cell::Number(n) = self.cell(r, c) {
+                if !numbers.contains(&n) {
+                    numbers.push(n);
+                }
+            }
+        }
+
+        numbers
+    }
+
+    // indices of the symbols touching any digit of the number
+    fn symbols_adjacent_to_number(&self, n: usize) -> Vec<usize> {
+        let number = self.numbers[n];
+        let mut symbols = vec![];
+
+        for column in number.x..=number.y {
+            for (r, c) in self.neighbours(number.l, column, Adjacency::Diagonal) {
+                if let Cell::Symbol(s) = self.cell(r, c) {
+                    if !symbols.contains(&s) {
+                        symbols.push(s);
                     }
                 }
+            }
+        }
+
+        symbols
+    }
+
+    // numbers which are adjacent to at least one symbol
+    fn part_numbers(&self) -> Vec<&GridNumber> {
+        self.numbers.iter().filter(|n| n.marked).collect()
+    }
+
+    // every symbol which satisfies the rule, together with its ratio
+    fn gears(&self, rule: &GearRule) -> Vec<Gear> {
+        let mut gears = vec![];
+
+        for (index, symbol) in self.symbols.iter().enumerate() {
+            if !rule.symbols.contains(symbol.char) {
+                continue;
+            }
+
+            let numbers = self.numbers_adjacent_with(symbol.row, symbol.column, rule.adjacency);
+            if !rule.count.accepts(numbers.len()) {
+                continue;
+            }
+
+            let values = numbers.iter().map(|&n| self.numbers[n].number).collect::<Vec<u32>>();
+            gears.push(Gear{symbol: index, ratio: rule.reducer.reduce(&values), numbers});
+        }
+
+        gears
+    }
+
+    // None if a ratio or the sum overflowed
+    fn gear_ratio_sum(&self, rule: &GearRule) -> Option<u64> {
+        self.gears(rule).iter().try_fold(0u64, |acc, gear| acc.checked_add(gear.ratio?))
+    }
 
-                if char == '*' && match_count == 2 {
-                    gear_ratio_sum += gear_ratio;
+    // highlight of every cell, gears are decided by the rule
+    fn highlights(&self, rule: &GearRule) -> Vec<Vec<Highlight>> {
+        let mut gears = vec![false; self.symbols.len()];
+        for gear in self.gears(rule) {
+            gears[gear.symbol] = true;
+        }
+
+        self.cells.iter().map(|cells| cells.iter().map(|cell| match *cell {
+            Cell::Empty => Highlight::Empty,
+            Cell::Number(n) if self.numbers[n].marked => Highlight::PartNumber,
+            Cell::Number(_) => Highlight::NonPartNumber,
+            Cell::Symbol(s) if gears[s] => Highlight::Gear,
+            Cell::Symbol(s) => {
+                let symbol = self.symbols[s];
+                if self.numbers_adjacent_to(symbol.row, symbol.column).is_empty() {
+                    Highlight::OrphanSymbol
+                } else {
+                    Highlight::Symbol
                 }
             }
+        }).collect()).collect()
+    }
 
-            column += 1;
+    fn render_ansi(&self, rule: &GearRule) -> String {
+        let mut out = String::new();
+
+        for (row, highlights) in self.highlights(rule).iter().enumerate() {
+            for (column, highlight) in highlights.iter().enumerate() {
+                out.push_str(highlight.ansi());
+                out.push(self.chars[row][column]);
+                out.push_str("\x1b[0m");
+            }
+            out.push('\n');
         }
 
-        row += 1;
+        out
     }
 
-    let sum: u32 = numbers.iter().filter(|n| n.marked).fold(0, |acc, n| acc + n.number);
+    fn render_html(&self, rule: &GearRule) -> String {
+        let mut out = String::from(concat!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<style>\n",
+            ".empty { color: #999; }\n",
+            ".part { color: green; }\n",
+            ".nonpart { color: red; }\n",
+            ".gear { color: orange; font-weight: bold; }\n",
+            ".symbol { color: teal; }\n",
+            ".orphan { color: purple; }\n",
+            "</style>\n</head>\n<body>\n<pre>\n"
+        ));
 
-    println!("sum : {sum}");
-    println!("gear_ratio_sum : {gear_ratio_sum}");
+        for (row, highlights) in self.highlights(rule).iter().enumerate() {
+            for (column, highlight) in highlights.iter().enumerate() {
+                let escaped = match self.chars[row][column] {
+                    '<' => "&lt;".to_string(),
+                    '>' => "&gt;".to_string(),
+                    '&' => "&amp;".to_string(),
+                    c => c.to_string(),
+                };
+                out.push_str(&format!("<span class=\"{}\">{escaped}</span>", highlight.class()));
+            }
+            out.push('\n');
+        }
 
+        out.push_str("</pre>\n</body>\n</html>\n");
+        out
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
-struct GridNumber {
-    number: u32, // value of the parsed string
-    l: u32,      // height/line in the grid
-    x: u32,      // leftmost position
-    y: u32,      // rightmost position
-    marked: bool // marked or not
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a '*' surrounded by six numbers of ten digits each
+    const CROWDED: &str = "4000000000.4000000000
+..........*..........
+4000000000.4000000000
+";
+
+    // a symbol in the top left corner and numbers ending in the last column and row
+    const EDGES: &str = "*2..45
+3...$.
+..7..9
+";
+
+    #[test]
+    fn adjacency_on_the_grid_edges() {
+        let schematic = Schematic::parse(EDGES);
+        let values = |numbers: Vec<usize>| numbers.iter().map(|&n| schematic.numbers[n].number).collect::<Vec<u32>>();
+
+        assert_eq!(values(schematic.numbers_adjacent_to(0, 0)), vec![2, 3]);
+        assert_eq!(values(schematic.numbers_adjacent_to(1, 4)), vec![45, 9]);
+        assert_eq!(values(schematic.numbers_adjacent_to(2, 5)), Vec::<u32>::new());
+        assert_eq!(schematic.part_numbers().iter().map(|n| n.number).collect::<Vec<u32>>(), vec![2, 45, 3, 9]);
+    }
+
+    #[test]
+    fn symbols_adjacent_to_numbers_on_the_grid_edges() {
+        let schematic = Schematic::parse(EDGES);
+        let symbols = |value: u32| {
+            let n = schematic.numbers.iter().position(|number| number.number == value).unwrap();
+            schematic.symbols_adjacent_to_number(n).iter().map(|&s| schematic.symbols[s].char).collect::<String>()
+        };
+
+        assert_eq!(symbols(2), "*");
+        assert_eq!(symbols(3), "*");
+        assert_eq!(symbols(45), "$");
+        assert_eq!(symbols(9), "$");
+        assert_eq!(symbols(7), "");
+    }
+
+    // a gear, a symbol with a number, an orphan symbol and a number without symbols,
+    // with characters which have to be escaped in html
+    const HIGHLIGHTED: &str = "1*2.&
+.....
+5<.6.
+";
+
+    #[test]
+    fn highlights_of_every_cell() {
+        use Highlight::*;
+
+        let schematic = Schematic::parse(HIGHLIGHTED);
+        assert_eq!(schematic.highlights(&GearRule::new()), vec![
+            vec![PartNumber, Gear, PartNumber, Empty, OrphanSymbol],
+            vec![Empty, Empty, Empty, Empty, Empty],
+            vec![PartNumber, Symbol, Empty, NonPartNumber, Empty]
+        ]);
+
+        // without a rule matching the '*' it is an ordinary symbol
+        let rule = GearRule{count: Count::Exactly(3), ..GearRule::new()};
+        assert_eq!(schematic.highlights(&rule)[0][1], Symbol);
+    }
+
+    #[test]
+    fn render_ansi_colors_every_cell() {
+        let ansi = Schematic::parse(HIGHLIGHTED).render_ansi(&GearRule::new());
+        assert_eq!(ansi.lines().count(), 3);
+        assert!(ansi.starts_with("\x1b[32m1\x1b[0m\x1b[1;33m*\x1b[0m\x1b[32m2\x1b[0m\x1b[2m.\x1b[0m\x1b[35m&\x1b[0m\n"));
+    }
+
+    #[test]
+    fn render_html_escapes_symbols() {
+        let html = Schematic::parse(HIGHLIGHTED).render_html(&GearRule::new());
+        assert!(html.contains(concat!(
+            "<span class=\"part\">1</span><span class=\"gear\">*</span><span class=\"part\">2</span>",
+            "<span class=\"empty\">.</span><span class=\"orphan\">&amp;</span>\n"
+        )));
+        assert!(html.contains(concat!(
+            "<span class=\"part\">5</span><span class=\"symbol\">&lt;</span><span class=\"empty\">.</span>",
+            "<span class=\"nonpart\">6</span><span class=\"empty\">.</span>\n"
+        )));
+        assert!(!html.contains(">&<") && !html.contains("><<"));
+    }
+
+    #[test]
+    fn product_overflow_is_reported() {
+        let schematic = Schematic::parse(&CROWDED.replace(".*.", "4*4"));
+        let rule = GearRule{count: Count::AtLeast(2), ..GearRule::new()};
+        let gears = schematic.gears(&rule);
+
+        assert_eq!(gears.len(), 1);
+        assert_eq!(gears[0].numbers.len(), 6);
+        assert_eq!(gears[0].ratio, None);
+        assert_eq!(schematic.gear_ratio_sum(&rule), None);
+    }
+
+    #[test]
+    fn sum_of_large_numbers_fits() {
+        let schematic = Schematic::parse(CROWDED);
+        let rule = GearRule{count: Count::AtLeast(2), reducer: Reducer::Sum, ..GearRule::new()};
+        assert_eq!(schematic.gear_ratio_sum(&rule), Some(16_000_000_000));
+    }
+
+    #[test]
+    fn puzzle_rule_on_sample() {
+        let sample = "467..114..\n...*......\n..35..633.\n......#...\n617*......\n.....+.58.\n..592.....\n......755.\n...$.*....\n.664.598..\n";
+        assert_eq!(Schematic::parse(sample).gear_ratio_sum(&GearRule::new()), Some(467835));
+    }
+}
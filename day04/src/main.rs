@@ -1,39 +1,256 @@
-use std::{fs::File, io::{BufReader, BufRead}, collections::VecDeque};
+use std::{fs::File, io::{BufReader, BufRead}};
+use num_bigint::BigUint;
 
 fn main() {
     let file = File::open("day04/data/input.txt").expect("file failed");
     let reader = BufReader::new(file);
-    let mut sum_scores = 0;
-    let mut sum_totals = 0;
+    let mut matches = vec![];
 
-    let mut copies = VecDeque::<i64>::new();
     for line in reader.lines() {
         if let Ok(line) = line {
             let card  = Card::parse(&line);
-            let matches = card.matches();
-            let score = calc_points(matches);
-            let total = 1 + copies.pop_front().unwrap_or(0); 
-
-            // updates for current card
-            println!("matches: {matches}");
-            println!("score: {score}");
-            println!("total: {total}");
-
-            // updates for future cards
-            if matches as usize > copies.len() {
-                copies.resize_with(matches as usize, || 0);
-            }
-
-            for (offset, copy_count) in std::iter::repeat(total).take(matches as usize).enumerate() {
-                copies[offset] += copy_count;
-            }
-            sum_scores += score;
-            sum_totals += total;
+            matches.push(card.matches() as usize);
         }
     }
 
+    let cascade = Cascade::new(NextK);
+    let result = cascade.run::<u64>(&matches).expect("card copies overflowed");
+    let mut sum_scores = 0u64;
+
+    for (&matches, total) in matches.iter().zip(&result.copies) {
+        let score = Doubling.score::<u64>(matches).expect("card score overflowed");
+
+        // updates for current card
+        println!("matches: {matches}");
+        println!("score: {score}");
+        println!("total: {total}");
+
+        sum_scores = sum_scores.checked_add(score).expect("sum of scores overflowed");
+    }
+
     println!("sum_scores: {sum_scores}");
-    println!("sum_totals: {sum_totals}");
+
+    // other scoring schemes for the same cards
+    println!("sum_scores linear: {}", sum_scores_with::<_, u64>(&Linear, &matches).expect("scores overflowed"));
+    println!("sum_scores fibonacci: {}", sum_scores_with::<_, BigUint>(&Fibonacci, &matches).expect("big integers overflowed"));
+    println!("sum_scores table: {}", sum_scores_with::<_, u64>(&Table(vec![0, 1, 3, 6, 10]), &matches).expect("scores overflowed"));
+    println!("sum_totals: {}", result.total);
+
+    // other copy rules on the same cards, big integers never overflow
+    let offsets = Cascade::new(Offsets(vec![1, 3, 5, 7, 9])).run::<BigUint>(&matches).expect("big integers overflowed");
+    println!("sum_totals with odd offsets: {}", offsets.total);
+
+    let doubled = Cascade::new(Multiplied{rule: NextK, factor: 2}).run::<BigUint>(&matches).expect("big integers overflowed");
+    println!("sum_totals with doubled copies: {}", doubled.total);
+
+    let capped = Cascade::new(Capped{rule: NextK, cap: 2}).run::<u64>(&matches);
+    match capped {
+        Some(capped) => println!("sum_totals with at most two copied cards: {}", capped.total),
+        None => println!("sum_totals with at most two copied cards: overflow"),
+    }
+}
+
+// which cards a winning card copies, as offsets from the winning card and copies per instance,
+// None signals an overflow
+trait CopyRule {
+    fn targets(&self, matches: usize) -> Option<Vec<(usize, u64)>>;
+}
+
+// the puzzle's rule: one copy of each of the next k cards
+struct NextK;
+
+// one copy of the card at the i-th offset for the i-th match
+struct Offsets(Vec<usize>);
+
+// the copies of another rule multiplied by a factor
+struct Multiplied<R: CopyRule> {
+    rule: R,
+    factor: u64
+}
+
+// another rule, but no win copies more than cap cards
+struct Capped<R: CopyRule> {
+    rule: R,
+    cap: usize
+}
+
+impl CopyRule for NextK {
+    fn targets(&self, matches: usize) -> Option<Vec<(usize, u64)>> {
+        Some((1..=matches).map(|offset| (offset, 1)).collect())
+    }
+}
+
+impl CopyRule for Offsets {
+    fn targets(&self, matches: usize) -> Option<Vec<(usize, u64)>> {
+        Some(self.0.iter().take(matches).map(|&offset| (offset, 1)).collect())
+    }
+}
+
+impl<R: CopyRule> CopyRule for Multiplied<R> {
+    fn targets(&self, matches: usize) -> Option<Vec<(usize, u64)>> {
+        self.rule.targets(matches)?.into_iter().map(|(offset, copies)| Some((offset, copies.checked_mul(self.factor)?))).collect()
+    }
+}
+
+impl<R: CopyRule> CopyRule for Capped<R> {
+    fn targets(&self, matches: usize) -> Option<Vec<(usize, u64)>> {
+        let mut targets = self.rule.targets(matches)?;
+        targets.truncate(self.cap);
+        Some(targets)
+    }
+}
+
+// number type for card counts and scores, None signals an overflow
+trait Tally: Clone + std::fmt::Display {
+    fn zero() -> Self;
+    fn one() -> Self;
+    fn from_u64(n: u64) -> Self;
+    fn add_scaled(&self, other: &Self, factor: u64) -> Option<Self>;
+}
+
+impl Tally for u64 {
+    fn zero() -> Self {
+        0
+    }
+
+    fn one() -> Self {
+        1
+    }
+
+    fn from_u64(n: u64) -> Self {
+        n
+    }
+
+    fn add_scaled(&self, other: &Self, factor: u64) -> Option<Self> {
+        other.checked_mul(factor).and_then(|scaled| self.checked_add(scaled))
+    }
+}
+
+impl Tally for BigUint {
+    fn zero() -> Self {
+        BigUint::from(0u32)
+    }
+
+    fn one() -> Self {
+        BigUint::from(1u32)
+    }
+
+    fn from_u64(n: u64) -> Self {
+        BigUint::from(n)
+    }
+
+    fn add_scaled(&self, other: &Self, factor: u64) -> Option<Self> {
+        Some(self + other * factor)
+    }
+}
+
+// points of a card with the given number of matches
+trait ScoringPolicy {
+    fn score<T: Tally>(&self, matches: usize) -> Option<T>;
+}
+
+// the puzzle's scoring: 1 point for the first match, doubled for every further match
+struct Doubling;
+
+// 1 point per match
+struct Linear;
+
+// 1, 2, 3, 5, 8, ... points for 1, 2, 3, 4, 5, ... matches
+struct Fibonacci;
+
+// points looked up by number of matches, more matches than entries score the last entry
+struct Table(Vec<u64>);
+
+impl ScoringPolicy for Doubling {
+    fn score<T: Tally>(&self, matches: usize) -> Option<T> {
+        if matches == 0 {
+            return Some(T::zero());
+        }
+
+        let mut score = T::one();
+        for _ in 1..matches {
+            score = score.add_scaled(&score, 1)?;
+        }
+
+        Some(score)
+    }
+}
+
+impl ScoringPolicy for Linear {
+    fn score<T: Tally>(&self, matches: usize) -> Option<T> {
+        Some(T::from_u64(matches as u64))
+    }
+}
+
+impl ScoringPolicy for Fibonacci {
+    fn score<T: Tally>(&self, matches: usize) -> Option<T> {
+        if matches == 0 {
+            return Some(T::zero());
+        }
+
+        let (mut a, mut b) = (T::one(), T::one());
+        for _ in 1..matches {
+            let next = a.add_scaled(&b, 1)?;
+            a = b;
+            b = next;
+        }
+
+        Some(b)
+    }
+}
+
+impl ScoringPolicy for Table {
+    fn score<T: Tally>(&self, matches: usize) -> Option<T> {
+        let points = self.0.get(matches).or(self.0.last()).copied().unwrap_or(0);
+        Some(T::from_u64(points))
+    }
+}
+
+fn sum_scores_with<P: ScoringPolicy, T: Tally>(policy: &P, matches: &[usize]) -> Option<T> {
+    let mut sum = T::zero();
+
+    for &k in matches {
+        sum = sum.add_scaled(&policy.score::<T>(k)?, 1)?;
+    }
+
+    Some(sum)
+}
+
+#[derive(Debug)]
+struct CascadeResult<T> {
+    copies: Vec<T>, // instances of every card, including the original
+    total: T
+}
+
+struct Cascade {
+    rule: Box<dyn CopyRule>
+}
+
+impl Cascade {
+    fn new(rule: impl CopyRule + 'static) -> Cascade {
+        Cascade { rule: Box::new(rule) }
+    }
+
+    // copies of cards past the last one are dropped
+    fn run<T: Tally>(&self, matches: &[usize]) -> Option<CascadeResult<T>> {
+        let mut copies = vec![T::one(); matches.len()];
+
+        for (index, &k) in matches.iter().enumerate() {
+            let instances = copies[index].clone();
+            for (offset, factor) in self.rule.targets(k)? {
+                if let Some(copy) = copies.get_mut(index + offset) {
+                    *copy = copy.add_scaled(&instances, factor)?;
+                }
+            }
+        }
+
+        let mut total = T::zero();
+        for copy in &copies {
+            total = total.add_scaled(copy, 1)?;
+        }
+
+        Some(CascadeResult { copies, total })
+    }
 }
 
 #[derive(Debug)]
@@ -70,7 +287,12 @@ impl Card {
         return card;
     }
 
+    // counted with a popcount where the numbers fit into the bitset, otherwise by searching the sorted numbers
     fn matches(&self) -> i64 {
+        self.matches_bits::<2>().unwrap_or_else(|_| self.matches_sorted())
+    }
+
+    fn matches_sorted(&self) -> i64 {
         let mut k = 0;
 
         for w in &self.winners {
@@ -81,12 +303,191 @@ impl Card {
 
         k
     }
+
+    // winners and owned numbers as bitsets, duplicates are rejected since a bitset would merge them
+    fn bits<const N: usize>(&self) -> Result<(Bitset<N>, Bitset<N>), BitsError> {
+        let mut winners = Bitset::<N>::new();
+        let mut owned = Bitset::<N>::new();
+
+        for &w in &self.winners {
+            if !winners.insert(w)? {
+                return Err(BitsError::Duplicate);
+            }
+        }
+
+        for &o in &self.owned {
+            if !owned.insert(o)? {
+                return Err(BitsError::Duplicate);
+            }
+        }
+
+        Ok((winners, owned))
+    }
+
+    // same as matches_sorted, but counted with a popcount
+    fn matches_bits<const N: usize>(&self) -> Result<i64, BitsError> {
+        let (winners, owned) = self.bits::<N>()?;
+        Ok(winners.intersection_count(&owned) as i64)
+    }
 }
 
-fn calc_points(matches: i64) -> i64 {
-    if matches >= 1 {
-        1 << (matches-1)
-    } else {
-        0
+#[derive(Debug)]
+enum BitsError {
+    OutOfRange, // a number does not fit into the bitset
+    Duplicate   // a number appears twice on the same side of a card
+}
+
+// set of the numbers 0..64*N
+#[derive(Debug, Clone, Copy)]
+struct Bitset<const N: usize>([u64; N]);
+
+impl<const N: usize> Bitset<N> {
+    fn new() -> Bitset<N> {
+        Bitset([0; N])
+    }
+
+    // returns whether the number was not in the set yet
+    fn insert(&mut self, n: i64) -> Result<bool, BitsError> {
+        if n < 0 || n >= 64 * N as i64 {
+            return Err(BitsError::OutOfRange);
+        }
+
+        let (word, bit) = (n as usize / 64, n as usize % 64);
+        let new = self.0[word] & (1 << bit) == 0;
+        self.0[word] |= 1 << bit;
+        Ok(new)
+    }
+
+    fn intersection_count(&self, other: &Bitset<N>) -> u32 {
+        self.0.iter().zip(other.0.iter()).map(|(a, b)| (a & b).count_ones()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubling_fits_up_to_64_matches() {
+        assert_eq!(Doubling.score::<u64>(0), Some(0));
+        assert_eq!(Doubling.score::<u64>(1), Some(1));
+        assert_eq!(Doubling.score::<u64>(4), Some(8));
+        assert_eq!(Doubling.score::<u64>(64), Some(1 << 63));
+        assert_eq!(Doubling.score::<u64>(65), None);
+    }
+
+    #[test]
+    fn doubling_with_big_integers_never_overflows() {
+        assert_eq!(Doubling.score::<BigUint>(65), Some(BigUint::from(1u32) << 64));
+        assert_eq!(Doubling.score::<BigUint>(200), Some(BigUint::from(1u32) << 199));
+    }
+
+    #[test]
+    fn linear_and_fibonacci_scores() {
+        assert_eq!((0..6).map(|k| Linear.score::<u64>(k).unwrap()).collect::<Vec<u64>>(), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!((0..8).map(|k| Fibonacci.score::<u64>(k).unwrap()).collect::<Vec<u64>>(), vec![0, 1, 2, 3, 5, 8, 13, 21]);
+        assert_eq!(Fibonacci.score::<u64>(92), Some(12200160415121876738));
+        assert_eq!(Fibonacci.score::<u64>(93), None);
+    }
+
+    #[test]
+    fn table_scores_with_fallback_to_the_last_entry() {
+        let table = Table(vec![0, 1, 3, 6]);
+        assert_eq!((0..4).map(|k| table.score::<u64>(k).unwrap()).collect::<Vec<u64>>(), vec![0, 1, 3, 6]);
+        assert_eq!(table.score::<u64>(10), Some(6));
+        assert_eq!(Table(vec![]).score::<u64>(3), Some(0));
+    }
+
+    #[test]
+    fn sum_scores_on_sample() {
+        let matches = [4, 2, 2, 1, 0, 0];
+        assert_eq!(sum_scores_with::<_, u64>(&Doubling, &matches), Some(13));
+        assert_eq!(sum_scores_with::<_, u64>(&Linear, &matches), Some(9));
+        assert_eq!(sum_scores_with::<_, u64>(&Fibonacci, &matches), Some(10));
+        assert_eq!(sum_scores_with::<_, u64>(&Doubling, &[]), Some(0));
+    }
+
+    #[test]
+    fn sum_scores_overflow_is_reported() {
+        assert_eq!(sum_scores_with::<_, u64>(&Doubling, &[64, 64]), None);
+        assert_eq!(sum_scores_with::<_, BigUint>(&Doubling, &[64, 64]), Some(BigUint::from(1u32) << 64));
+    }
+
+    #[test]
+    fn cascade_of_no_cards_is_empty() {
+        let result = Cascade::new(NextK).run::<u64>(&[]).expect("no cards cannot overflow");
+        assert!(result.copies.is_empty());
+        assert_eq!(result.total, 0);
+    }
+
+    #[test]
+    fn cascade_on_sample() {
+        let result = Cascade::new(NextK).run::<u64>(&[4, 2, 2, 1, 0, 0]).unwrap();
+        assert_eq!(result.copies, vec![1, 2, 4, 8, 14, 1]);
+        assert_eq!(result.total, 30);
+    }
+
+    #[test]
+    fn multiplied_factor_overflow_is_reported() {
+        let rule = Multiplied{rule: Multiplied{rule: NextK, factor: u64::MAX}, factor: 2};
+        assert_eq!(rule.targets(1), None);
+        assert!(Cascade::new(rule).run::<BigUint>(&[1, 0]).is_none());
+    }
+
+    #[test]
+    fn bitset_and_sorted_matching_agree() {
+        let cards = [
+            "Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53",
+            "Card 2: 13 32 20 16 61 | 61 30 68 82 17 32 24 19",
+            "Card 3:  1 21 53 59 44 | 69 82 63 72 16 21 14  1",
+            "Card 4: 41 92 73 84 69 | 59 84 76 51 58  5 54 83",
+            "Card 5: 87 83 26 28 32 | 88 30 70 12 93 22 82 36",
+            "Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11",
+            "Card 7:  0 63 64 127 | 127 64 63 0 1",
+        ];
+        for card in cards.iter().map(|s| Card::parse(s)) {
+            assert_eq!(card.matches_bits::<2>().unwrap(), card.matches_sorted());
+            assert_eq!(card.matches(), card.matches_sorted());
+        }
+    }
+
+    #[test]
+    fn bitset_bounds() {
+        let mut bits = Bitset::<1>::new();
+        assert!(bits.insert(0).unwrap());
+        assert!(bits.insert(63).unwrap());
+        assert!(matches!(bits.insert(64), Err(BitsError::OutOfRange)));
+        assert!(matches!(bits.insert(-1), Err(BitsError::OutOfRange)));
+
+        let mut bits = Bitset::<2>::new();
+        assert!(bits.insert(64).unwrap());
+        assert!(bits.insert(127).unwrap());
+        assert!(!bits.insert(127).unwrap());
+        assert!(matches!(bits.insert(128), Err(BitsError::OutOfRange)));
+    }
+
+    #[test]
+    fn out_of_range_falls_back_to_sorted() {
+        let card = Card::parse("Card 1: 5 128 | 128 7");
+        assert!(matches!(card.matches_bits::<2>(), Err(BitsError::OutOfRange)));
+        assert_eq!(card.matches_bits::<3>().unwrap(), 1);
+        assert_eq!(card.matches(), 1);
+
+        let card = Card::parse("Card 1: 5 -3 | -3 5 7");
+        assert!(matches!(card.matches_bits::<3>(), Err(BitsError::OutOfRange)));
+        assert_eq!(card.matches(), 2);
+    }
+
+    #[test]
+    fn duplicates_fall_back_to_sorted() {
+        // the sorted search counts every winner, so a repeated winner counts twice
+        let card = Card::parse("Card 1: 5 5 9 | 5 9");
+        assert!(matches!(card.matches_bits::<1>(), Err(BitsError::Duplicate)));
+        assert_eq!(card.matches(), card.matches_sorted());
+        assert_eq!(card.matches(), 3);
+
+        let card = Card::parse("Card 1: 5 9 | 9 9");
+        assert!(matches!(card.matches_bits::<1>(), Err(BitsError::Duplicate)));
+        assert_eq!(card.matches(), 1);
     }
 }
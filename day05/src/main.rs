@@ -1,29 +1,12 @@
-use std::vec;
+// the crate is on the 2015 edition, so the test dependency has to be declared
+#[cfg(test)]
+extern crate proptest;
+
+use std::{collections::{HashMap, VecDeque}, fmt, vec};
 
 fn main() {
     let input = std::fs::read_to_string("day05/data/input.txt").expect("failed to read file");
-    let mut lines = input.lines();
-    let start_values = lines.next().unwrap().split(':').skip(1).next().unwrap().split_ascii_whitespace().map(|s| s.parse::<i64>().unwrap()).collect::<Vec<i64>>();
-    let mut almanac = Almanac::new(start_values);
-
-    for line in lines {
-        if line.is_empty() {
-            continue;
-        }
-
-        if line.contains(':') {
-            almanac.maps.push(Map::new());
-            continue;
-        }
-
-        let nums = line.split_ascii_whitespace().map(|s| s.parse::<i64>().unwrap()).collect::<Vec<i64>>();
-        let range = Range{
-            dest_range_start: nums[0],
-            source_range_start: nums[1],
-            range_length: nums[2],
-        };
-        almanac.maps.last_mut().unwrap().ranges.push(range);
-    }
+    let almanac = Almanac::parse(&input);
 
     let mut dest_min = almanac.source_to_dest(almanac.start_values[0]);
 
@@ -44,6 +27,53 @@ fn main() {
     outputs.sort_by(|a,b| a.start.cmp(&b.start));
     let dest_min_ranges = outputs[0].start;
     println!("Part Two: {dest_min_ranges}");
+
+    // going backwards from the locations
+    println!("Every map is a bijection: {}", almanac.inverse().is_some());
+    match almanac.lowest_location_by_inverse() {
+        Some(lowest) => println!("Lowest location by inverse search: {lowest}"),
+        None => println!("Lowest location by inverse search: no seed reaches a location"),
+    }
+
+    let seeds = almanac.seeds_for_locations(&SimpleRange{start: 0, length: dest_min_ranges + 1});
+    println!("Seeds reaching a location up to {dest_min_ranges}: {seeds:?}");
+
+    // the whole chain as a single function
+    let composed = almanac.compose();
+    let composed_min_values = almanac.start_values.iter().map(|&seed| composed.apply(seed)).min().expect("no seeds");
+    println!("Part One by composed map: {composed_min_values}");
+
+    let mut composed_outputs = vec![];
+    for input in &almanac.start_ranges {
+        composed_outputs.append(&mut composed.map(input));
+    }
+    let composed_min = composed_outputs.iter().map(|range| range.start).min().expect("no seed ranges");
+    println!("Part Two by composed map: {composed_min}");
+
+    let first = almanac.maps.first().expect("almanac has no maps");
+    let last = almanac.maps.last().expect("almanac has no maps");
+    print!("{}", composed.export(&first.source, &last.dest));
+
+    // queries between arbitrary categories
+    match almanac.map_value("soil", "humidity", 81) {
+        Ok(humidity) => println!("soil 81 -> humidity {humidity}"),
+        Err(e) => println!("soil 81 -> humidity: {e}"),
+    }
+    match almanac.map_range("fertilizer", "location", &SimpleRange{start: 0, length: 100}) {
+        Ok(locations) => println!("fertilizer 0..100 -> location {:?}", RangeSet::from_ranges(locations).ranges),
+        Err(e) => println!("fertilizer 0..100 -> location: {e}"),
+    }
+    if let Err(e) = almanac.map_value("location", "seed", 0) {
+        println!("location 0 -> seed: {e}");
+    }
+
+    // the seed ranges and their locations as sets of numbers
+    let seeds = RangeSet::from_ranges(almanac.start_ranges.clone());
+    let locations = RangeSet::from_ranges(composed_outputs);
+    println!("{} seeds in {} ranges reach {} locations in {} ranges", seeds.len(), seeds.ranges.len(), locations.len(), locations.ranges.len());
+    println!("Numbers which are both seeds and locations: {}", seeds.intersection(&locations).len());
+    println!("Lowest location is itself a seed: {}", seeds.contains(dest_min_ranges));
+    println!("Every location up to {dest_min_ranges} is reached: {}", locations.contains_range(&SimpleRange{start: 0, length: dest_min_ranges + 1}));
 }
 
 #[derive(Debug)]
@@ -54,6 +84,33 @@ struct Almanac {
 }
 
 impl Almanac {
+    fn parse(input: &str) -> Almanac {
+        let mut lines = input.lines();
+        let start_values = lines.next().unwrap().split(':').skip(1).next().unwrap().split_ascii_whitespace().map(|s| s.parse::<i64>().unwrap()).collect::<Vec<i64>>();
+        let mut almanac = Almanac::new(start_values);
+
+        for line in lines {
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.contains(':') {
+                almanac.maps.push(Map::parse_header(line).expect("failed to parse map header"));
+                continue;
+            }
+
+            let nums = line.split_ascii_whitespace().map(|s| s.parse::<i64>().unwrap()).collect::<Vec<i64>>();
+            let range = Range{
+                dest_range_start: nums[0],
+                source_range_start: nums[1],
+                range_length: nums[2],
+            };
+            almanac.maps.last_mut().unwrap().ranges.push(range);
+        }
+
+        almanac
+    }
+
     fn new(start_values: Vec<i64>) -> Almanac {
         let mut almanac = Almanac {
             start_values,
@@ -94,37 +151,230 @@ impl Almanac {
 
         outputs
     }
+
+    // almanac going from locations back to seeds, only possible if every map is a bijection
+    fn inverse(&self) -> Option<Almanac> {
+        let mut maps = vec![];
+
+        for map in self.maps.iter().rev() {
+            if !map.is_bijective() {
+                return None;
+            }
+            maps.push(map.inverse());
+        }
+
+        Some(Almanac { start_values: vec![], start_ranges: vec![], maps })
+    }
+
+    // every number which the whole chain of maps sends into the locations
+    fn preimage(&self, locations: &RangeSet) -> RangeSet {
+        let mut sources = locations.clone();
+        for map in self.maps.iter().rev() {
+            sources = map.preimage(&sources);
+        }
+        sources
+    }
+
+    // ranges of start values which end up in the given locations
+    fn seeds_for_locations(&self, locations: &SimpleRange) -> Vec<SimpleRange> {
+        let seeds = RangeSet::from_ranges(self.start_ranges.clone());
+        self.preimage(&RangeSet::from_ranges(vec![*locations])).intersection(&seeds).ranges
+    }
+
+    // scans the locations upwards in blocks and narrows the first block which contains a seed
+    fn lowest_location_by_inverse(&self) -> Option<i64> {
+        let seeds = RangeSet::from_ranges(self.start_ranges.clone());
+        let has_seed = |locations: &SimpleRange| {
+            self.preimage(&RangeSet::from_ranges(vec![*locations])).intersection(&seeds).len() > 0
+        };
+
+        // everything above the largest number in the almanac is mapped onto itself
+        let mut limit = 0;
+        for range in &self.start_ranges {
+            limit = limit.max(range.start + range.length);
+        }
+        for map in &self.maps {
+            for range in &map.ranges {
+                limit = limit.max(range.source_range_start + range.range_length);
+                limit = limit.max(range.dest_range_start + range.range_length);
+            }
+        }
+
+        let block = 1 << 16;
+        let mut start = 0;
+        while start < limit {
+            let mut found = SimpleRange{start, length: block};
+            if has_seed(&found) {
+                while found.length > 1 {
+                    let lower = SimpleRange{start: found.start, length: found.length / 2};
+                    let upper = SimpleRange{start: found.start + lower.length, length: found.length - lower.length};
+                    found = if has_seed(&lower) { lower } else { upper };
+                }
+                return Some(found.start);
+            }
+            start += block;
+        }
+
+        None
+    }
 }
 
 #[derive(Debug)]
 struct Map {
+    source: String, // category of the inputs
+    dest: String,   // category of the outputs
     ranges: Vec<Range>
 }
 
+#[derive(Debug, PartialEq)]
+enum ChainError {
+    UnknownCategory(String),
+    NoChain { source: String, dest: String }
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChainError::UnknownCategory(category) => write!(f, "unknown category '{}'", category),
+            ChainError::NoChain { source, dest } => write!(f, "no chain of maps leads from '{}' to '{}'", source, dest),
+        }
+    }
+}
+
+impl Almanac {
+    // maps leading from source to dest, found with a breadth-first search over the categories
+    fn chain(&self, source: &str, dest: &str) -> Result<Vec<&Map>, ChainError> {
+        let mut edges = HashMap::<&str, Vec<usize>>::new();
+        for (index, map) in self.maps.iter().enumerate() {
+            edges.entry(&map.source).or_default().push(index);
+            edges.entry(&map.dest).or_default();
+        }
+
+        for category in [source, dest] {
+            if !edges.contains_key(category) {
+                return Err(ChainError::UnknownCategory(category.to_string()));
+            }
+        }
+
+        // map which was used to reach each category
+        let mut reached_by = HashMap::<&str, Option<usize>>::new();
+        let mut queue = VecDeque::from([source]);
+        reached_by.insert(source, None);
+
+        while let Some(category) = queue.pop_front() {
+            if category == dest {
+                break;
+            }
+
+            for &index in &edges[category] {
+                let next = self.maps[index].dest.as_str();
+                if !reached_by.contains_key(next) {
+                    reached_by.insert(next, Some(index));
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        if !reached_by.contains_key(dest) {
+            return Err(ChainError::NoChain { source: source.to_string(), dest: dest.to_string() });
+        }
+
+        let mut chain = vec![];
+        let mut category = dest;
+        while let Some(index) = reached_by[category] {
+            chain.push(&self.maps[index]);
+            category = &self.maps[index].source;
+        }
+        chain.reverse();
+
+        Ok(chain)
+    }
+
+    fn map_value(&self, source: &str, dest: &str, value: i64) -> Result<i64, ChainError> {
+        Ok(self.chain(source, dest)?.iter().fold(value, |value, map| map.source_to_dest(value)))
+    }
+
+    fn map_range(&self, source: &str, dest: &str, range: &SimpleRange) -> Result<Vec<SimpleRange>, ChainError> {
+        let mut ranges = vec![*range];
+
+        for map in self.chain(source, dest)? {
+            ranges = ranges.iter().flat_map(|range| map.map(range)).collect();
+        }
+
+        Ok(ranges)
+    }
+}
+
 impl Map {
-    fn new() -> Map {
+    fn new(source: &str, dest: &str) -> Map {
         Map {
+            source: source.to_string(),
+            dest: dest.to_string(),
             ranges: vec![]
         }
     }
+
+    // header lines look like "seed-to-soil map:"
+    fn parse_header(line: &str) -> Option<Map> {
+        let name = line.trim().strip_suffix("map:")?.trim();
+        let (source, dest) = name.split_once("-to-")?;
+        Some(Map::new(source, dest))
+    }
+
+    fn inverse(&self) -> Map {
+        Map {
+            source: self.dest.clone(),
+            dest: self.source.clone(),
+            ranges: self.ranges.iter().map(|range| range.inverse()).collect()
+        }
+    }
+
+    // every source which the map sends into the destinations, where ranges overlap the first one wins
+    // as in source_to_dest, and numbers outside of every source range are mapped onto themselves
+    fn preimage(&self, dests: &RangeSet) -> RangeSet {
+        let mut covered = RangeSet::new();
+        let mut sources = vec![];
+
+        for range in &self.ranges {
+            let source = RangeSet::from_ranges(vec![SimpleRange{start: range.source_range_start, length: range.range_length}]);
+            let shift = range.source_range_start - range.dest_range_start;
+            let shifted = RangeSet::from_ranges(dests.ranges.iter().map(|d| SimpleRange{start: d.start + shift, length: d.length}).collect());
+
+            sources.extend(shifted.intersection(&source.difference(&covered)).ranges);
+            covered = covered.union(&source);
+        }
+
+        sources.extend(dests.difference(&covered).ranges);
+        RangeSet::from_ranges(sources)
+    }
+
+    // the sources and the destinations cover the same numbers without overlaps,
+    // so swapping them yields the inverse function
+    fn is_bijective(&self) -> bool {
+        let sources = RangeSet::from_ranges(self.ranges.iter().map(|r| SimpleRange{start: r.source_range_start, length: r.range_length}).collect());
+        let dests = RangeSet::from_ranges(self.ranges.iter().map(|r| SimpleRange{start: r.dest_range_start, length: r.range_length}).collect());
+        let total: i64 = self.ranges.iter().map(|r| r.range_length).sum();
+
+        // equal lengths before and after merging means nothing overlapped
+        sources.len() == total && dests.len() == total && sources == dests
+    }
 }
 
 impl Map {
-    // Map a range of sources to multiple destination ranges.
+    // Map a range of sources to multiple destination ranges, where ranges overlap the first one wins.
     fn map(&self, sources: &SimpleRange) -> Vec<SimpleRange> {
-        let mut intersections = Vec::<SimpleRange>::new();
+        let mut unmatched = RangeSet::from_ranges(vec![*sources]);
         let mut destinations = Vec::<SimpleRange>::new();
 
         for range in &self.ranges {
             if let Some(intersection) = range.intersection(sources) {
-                let translated = range.translate(&intersection);
-                intersections.push(intersection);
-                destinations.push(translated);
+                let matched = unmatched.intersection(&RangeSet::from_ranges(vec![intersection]));
+                destinations.extend(matched.ranges.iter().map(|part| range.translate(part)));
+                unmatched = unmatched.difference(&matched);
             }
         }
 
-        let mut unmatcheds = sources.minus_disjoint_subsets(intersections);
-        destinations.append(&mut unmatcheds);
+        destinations.extend(unmatched.ranges);
         destinations
     }
 
@@ -138,6 +388,136 @@ impl Map {
     }
 }
 
+// part of a piecewise-linear function, numbers in start..end are shifted by offset
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Piece {
+    start: i64,
+    end: i64,
+    offset: i64
+}
+
+// sorted pieces without gaps, covering every number from 0 to i64::MAX
+#[derive(Debug)]
+struct Composed {
+    pieces: Vec<Piece>
+}
+
+impl Almanac {
+    fn compose(&self) -> Composed {
+        let mut composed = Composed::identity();
+        for map in &self.maps {
+            composed = composed.then(&Composed::from_map(map));
+        }
+        composed
+    }
+}
+
+impl Composed {
+    fn identity() -> Composed {
+        Composed {
+            pieces: vec![Piece{start: 0, end: i64::MAX, offset: 0}]
+        }
+    }
+
+    // overlapping sources are resolved in favour of the first declared range, as in Map::source_to_dest
+    fn from_map(map: &Map) -> Composed {
+        let mut covered = RangeSet::new();
+        let mut pieces = vec![];
+        for range in &map.ranges {
+            let source = RangeSet::from_ranges(vec![SimpleRange{start: range.source_range_start, length: range.range_length}]);
+            let offset = range.dest_range_start - range.source_range_start;
+
+            for part in source.difference(&covered).ranges {
+                let start = part.start.max(0);
+                if start < part.end() {
+                    pieces.push(Piece{start, end: part.end(), offset});
+                }
+            }
+            covered = covered.union(&source);
+        }
+        pieces.sort_by_key(|piece| piece.start);
+
+        // numbers between the pieces are mapped onto themselves
+        let mut filled = vec![];
+        let mut cursor = 0;
+        for piece in pieces {
+            if cursor < piece.start {
+                filled.push(Piece{start: cursor, end: piece.start, offset: 0});
+            }
+            cursor = piece.end;
+            filled.push(piece);
+        }
+        filled.push(Piece{start: cursor, end: i64::MAX, offset: 0});
+
+        Composed { pieces: filled }.coalesce()
+    }
+
+    // first self, then next
+    fn then(&self, next: &Composed) -> Composed {
+        let mut pieces = vec![];
+
+        for piece in &self.pieces {
+            let image_start = piece.start + piece.offset;
+            let image_end = piece.end + piece.offset;
+
+            let first = next.pieces.partition_point(|p| p.end <= image_start);
+            for next_piece in next.pieces[first..].iter().take_while(|p| p.start < image_end) {
+                pieces.push(Piece {
+                    start: image_start.max(next_piece.start) - piece.offset,
+                    end: image_end.min(next_piece.end) - piece.offset,
+                    offset: piece.offset + next_piece.offset
+                });
+            }
+        }
+
+        Composed { pieces }.coalesce()
+    }
+
+    // merge neighbouring pieces with the same offset
+    fn coalesce(self) -> Composed {
+        let mut pieces: Vec<Piece> = vec![];
+
+        for piece in self.pieces {
+            match pieces.last_mut() {
+                Some(last) if last.end == piece.start && last.offset == piece.offset => last.end = piece.end,
+                _ => pieces.push(piece),
+            }
+        }
+
+        Composed { pieces }
+    }
+
+    // negative numbers are outside of the pieces and stay as they are
+    fn apply(&self, value: i64) -> i64 {
+        let index = self.pieces.partition_point(|p| p.end <= value);
+        match self.pieces.get(index) {
+            Some(piece) if piece.start <= value => value + piece.offset,
+            _ => value,
+        }
+    }
+
+    fn map(&self, sources: &SimpleRange) -> Vec<SimpleRange> {
+        let end = sources.start + sources.length;
+        let first = self.pieces.partition_point(|p| p.end <= sources.start);
+
+        self.pieces[first..].iter().take_while(|p| p.start < end).map(|piece| {
+            let start = sources.start.max(piece.start);
+            SimpleRange{start: start + piece.offset, length: end.min(piece.end) - start}
+        }).collect()
+    }
+
+    // the non-identity pieces in the almanac's own map format
+    fn export(&self, source: &str, dest: &str) -> String {
+        let mut out = format!("{source}-to-{dest} map:\n");
+
+        for piece in self.pieces.iter().filter(|p| p.offset != 0) {
+            out.push_str(&format!("{} {} {}\n", piece.start + piece.offset, piece.start, piece.end - piece.start));
+        }
+
+        out
+    }
+}
+
 #[derive(Debug)]
 struct Range {
     source_range_start: i64,
@@ -178,48 +558,415 @@ impl Range {
 
     // Map a single point
     fn source_to_dest(&self, source: i64) -> Option<i64> {
-        if self.source_range_start <= source && source < self.source_range_start + self.range_length - 1 {
+        if self.source_range_start <= source && source < self.source_range_start + self.range_length {
             return Some(source - self.source_range_start + self.dest_range_start);
         }
 
         return None;
     }
+
+    // same range with source and destination swapped
+    fn inverse(&self) -> Range {
+        Range {
+            source_range_start: self.dest_range_start,
+            dest_range_start: self.source_range_start,
+            range_length: self.range_length
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 struct SimpleRange {
     start: i64,
     length: i64
 }
 
 impl SimpleRange {
-    // idea: collect every endpoint and take the inverted ranges
-    fn minus_disjoint_subsets(&self, mut ranges: Vec<SimpleRange>) -> Vec<SimpleRange> {
-        let mut points = vec![];
-        let mut results = vec![];
-        ranges.sort_by(|a,b| a.start.cmp(&b.start));
+    fn contains(&self, value: i64) -> bool {
+        self.start <= value && value < self.start + self.length
+    }
 
-        points.push(self.start);
-        for range in &ranges {
-            points.push(range.start - 1);
-            points.push(range.start + range.length);
+    fn intersection(&self, other: &SimpleRange) -> Option<SimpleRange> {
+        let start = self.start.max(other.start);
+        let end = (self.start + self.length).min(other.start + other.length);
+
+        if end <= start {
+            return None;
         }
-        points.push(self.start + self.length - 1);
 
-        let mut i = 0;
-        while i < points.len() {
-            let l = points[i+1] - points[i] + 1;
-            if l >= 1 {
-                let result = SimpleRange{
-                    start: points[i],
-                    length: l
-                };
-                results.push(result);
+        Some(SimpleRange { start, length: end - start })
+    }
+
+    fn end(&self) -> i64 {
+        self.start + self.length
+    }
+}
+
+// set of numbers as sorted, disjoint and non-touching ranges
+#[derive(Debug, Clone, PartialEq)]
+struct RangeSet {
+    ranges: Vec<SimpleRange>
+}
+
+impl RangeSet {
+    fn new() -> RangeSet {
+        RangeSet { ranges: vec![] }
+    }
+
+    // accepts overlapping, touching, unsorted and empty ranges
+    fn from_ranges(mut ranges: Vec<SimpleRange>) -> RangeSet {
+        ranges.retain(|range| range.length > 0);
+        ranges.sort_by_key(|range| range.start);
+
+        let mut set = RangeSet::new();
+        for range in ranges {
+            match set.ranges.last_mut() {
+                Some(last) if range.start <= last.end() => last.length = last.end().max(range.end()) - last.start,
+                _ => set.ranges.push(range),
             }
+        }
 
-            i += 2;
+        set
+    }
+
+    // amount of numbers in the set
+    fn len(&self) -> i64 {
+        self.ranges.iter().map(|range| range.length).sum()
+    }
+
+    fn contains(&self, value: i64) -> bool {
+        let index = self.ranges.partition_point(|range| range.end() <= value);
+        self.ranges.get(index).is_some_and(|range| range.contains(value))
+    }
+
+    fn contains_range(&self, other: &SimpleRange) -> bool {
+        if other.length <= 0 {
+            return true;
+        }
+
+        let index = self.ranges.partition_point(|range| range.end() <= other.start);
+        self.ranges.get(index).is_some_and(|range| range.start <= other.start && other.end() <= range.end())
+    }
+
+    fn union(&self, other: &RangeSet) -> RangeSet {
+        RangeSet::from_ranges(self.ranges.iter().chain(other.ranges.iter()).copied().collect())
+    }
+
+    fn intersection(&self, other: &RangeSet) -> RangeSet {
+        let mut ranges = vec![];
+        let (mut i, mut j) = (0, 0);
+
+        while i < self.ranges.len() && j < other.ranges.len() {
+            let (a, b) = (self.ranges[i], other.ranges[j]);
+            if let Some(intersection) = a.intersection(&b) {
+                ranges.push(intersection);
+            }
+
+            if a.end() < b.end() {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+
+        RangeSet { ranges }
+    }
+
+    fn difference(&self, other: &RangeSet) -> RangeSet {
+        let mut ranges = vec![];
+        let mut j = 0;
+
+        for range in &self.ranges {
+            let mut start = range.start;
+
+            // skip everything of other which ends before this range
+            while j < other.ranges.len() && other.ranges[j].end() <= start {
+                j += 1;
+            }
+
+            let mut k = j;
+            while k < other.ranges.len() && other.ranges[k].start < range.end() {
+                let hole = other.ranges[k];
+                if start < hole.start {
+                    ranges.push(SimpleRange { start, length: hole.start - start });
+                }
+                start = start.max(hole.end());
+                k += 1;
+            }
+
+            if start < range.end() {
+                ranges.push(SimpleRange { start, length: range.end() - start });
+            }
+        }
+
+        RangeSet { ranges }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use proptest::prelude::*;
+
+    const SAMPLE: &str = "seeds: 79 14 55 13
+
+seed-to-soil map:
+50 98 2
+52 50 48
+
+soil-to-fertilizer map:
+0 15 37
+37 52 2
+39 0 15
+
+fertilizer-to-water map:
+49 53 8
+0 11 42
+42 0 7
+57 7 4
+
+water-to-light map:
+88 18 7
+18 25 70
+
+light-to-temperature map:
+45 77 23
+81 45 19
+68 64 13
+
+temperature-to-humidity map:
+0 69 1
+1 0 69
+
+humidity-to-location map:
+60 56 37
+56 93 4
+";
+
+    // a map with identity gaps and overlapping sources, so it is no bijection
+    const GAPS: &str = "seeds: 0 40
+
+a-to-b map:
+20 0 5
+0 3 4
+30 25 3
+";
+
+    // overlapping ranges with the lower source declared second, so the first range shadows 3..5 of it
+    const SHADOWED: &str = "seeds: 0 10
+
+a-to-b map:
+0 3 4
+20 0 5
+";
+
+    // every number below the limit whose location lies in the range, by brute force
+    fn preimage_by_scan(almanac: &Almanac, locations: &SimpleRange, limit: i64) -> RangeSet {
+        let hits = (0..limit).filter(|&n| locations.contains(almanac.source_to_dest(n))).map(|n| SimpleRange{start: n, length: 1});
+        RangeSet::from_ranges(hits.collect())
+    }
+
+    #[test]
+    fn seeds_are_preimages_of_their_locations() {
+        let almanac = Almanac::parse(SAMPLE);
+        for &seed in &almanac.start_values {
+            let location = almanac.source_to_dest(seed);
+            let seeds = almanac.preimage(&RangeSet::from_ranges(vec![SimpleRange{start: location, length: 1}]));
+            assert!(seeds.contains(seed), "seed {} is not a preimage of {}", seed, location);
+        }
+    }
+
+    #[test]
+    fn inverse_search_matches_part_two() {
+        let almanac = Almanac::parse(SAMPLE);
+        assert!(almanac.inverse().is_some());
+        assert_eq!(almanac.lowest_location_by_inverse(), Some(46));
+        assert_eq!(almanac.seeds_for_locations(&SimpleRange{start: 0, length: 47}), vec![SimpleRange{start: 82, length: 1}]);
+    }
+
+    #[test]
+    fn preimage_through_identity_gaps() {
+        for input in [SAMPLE, GAPS, SHADOWED] {
+            let almanac = Almanac::parse(input);
+            for start in (0..120).step_by(7) {
+                let locations = SimpleRange{start, length: 9};
+                assert_eq!(almanac.preimage(&RangeSet::from_ranges(vec![locations])), preimage_by_scan(&almanac, &locations, 200));
+            }
+        }
+    }
+
+    #[test]
+    fn seeds_for_locations_without_bijection() {
+        let almanac = Almanac::parse(GAPS);
+        assert!(almanac.inverse().is_none());
+
+        // 0..5 -> 20..25, 5..7 -> 2..4 since 3 and 4 are shadowed by the first range, 25..28 -> 30..33, the rest stays
+        let seeds = almanac.seeds_for_locations(&SimpleRange{start: 0, length: 8});
+        assert_eq!(seeds, vec![SimpleRange{start: 5, length: 3}]);
+        assert_eq!(almanac.lowest_location_by_inverse(), Some(2));
+    }
+
+    #[test]
+    fn overlapping_ranges_map_like_single_values() {
+        let almanac = Almanac::parse(GAPS);
+        let locations = RangeSet::from_ranges(almanac.map(&almanac.start_ranges[0]));
+        let by_value = RangeSet::from_ranges((0..40).map(|n| SimpleRange{start: almanac.source_to_dest(n), length: 1}).collect());
+        assert_eq!(locations, by_value);
+    }
+
+    #[test]
+    fn map_value_along_a_chain() {
+        let almanac = Almanac::parse(SAMPLE);
+        assert_eq!(almanac.map_value("soil", "humidity", 81), Ok(78));
+        assert_eq!(almanac.map_value("seed", "location", 79), Ok(82));
+        assert_eq!(almanac.map_value("seed", "seed", 79), Ok(79));
+        assert_eq!(almanac.chain("seed", "location").unwrap().len(), 7);
+    }
+
+    #[test]
+    fn map_range_along_a_chain() {
+        let almanac = Almanac::parse(SAMPLE);
+        assert_eq!(almanac.map_range("seed", "soil", &SimpleRange{start: 79, length: 14}), Ok(vec![SimpleRange{start: 81, length: 14}]));
+
+        let locations = almanac.map_range("seed", "location", &SimpleRange{start: 79, length: 14}).unwrap();
+        assert_eq!(locations.iter().map(|range| range.length).sum::<i64>(), 14);
+        assert_eq!(locations.iter().map(|range| range.start).min(), Some(46));
+    }
+
+    #[test]
+    fn chain_errors() {
+        let almanac = Almanac::parse(SAMPLE);
+        assert_eq!(almanac.map_value("dragon", "location", 1), Err(ChainError::UnknownCategory("dragon".to_string())));
+        assert_eq!(almanac.map_value("seed", "dragon", 1), Err(ChainError::UnknownCategory("dragon".to_string())));
+
+        let reversed = ChainError::NoChain { source: "location".to_string(), dest: "seed".to_string() };
+        assert_eq!(almanac.map_value("location", "seed", 1), Err(reversed));
+        assert!(almanac.map_range("humidity", "soil", &SimpleRange{start: 0, length: 5}).is_err());
+
+        assert_eq!(ChainError::UnknownCategory("dragon".to_string()).to_string(), "unknown category 'dragon'");
+        assert_eq!(almanac.chain("location", "seed").unwrap_err().to_string(), "no chain of maps leads from 'location' to 'seed'");
+    }
+
+    #[test]
+    fn map_headers() {
+        let map = Map::parse_header("seed-to-soil map:").unwrap();
+        assert_eq!((map.source.as_str(), map.dest.as_str()), ("seed", "soil"));
+        assert!(Map::parse_header("seed to soil map:").is_none());
+        assert!(Map::parse_header("seed-to-soil:").is_none());
+        assert!(Map::parse_header("seeds: 79 14 55 13").is_none());
+    }
+
+    #[test]
+    fn composed_map_keeps_declaration_order() {
+        let almanac = Almanac::parse(SHADOWED);
+        let composed = almanac.compose();
+        assert_eq!(almanac.source_to_dest(3), 0);
+        assert_eq!(composed.apply(3), 0);
+        assert_eq!((0..9).map(|n| composed.apply(n)).collect::<Vec<i64>>(), vec![20, 21, 22, 0, 1, 2, 3, 7, 8]);
+    }
+
+    #[test]
+    fn composed_map_agrees_with_chain() {
+        for input in [SAMPLE, GAPS, SHADOWED] {
+            let almanac = Almanac::parse(input);
+            let composed = almanac.compose();
+            for n in 0..200 {
+                assert_eq!(composed.apply(n), almanac.source_to_dest(n), "composed map disagrees for {}", n);
+            }
+
+            for start in (0..150).step_by(11) {
+                let sources = SimpleRange{start, length: 17};
+                let by_chain = RangeSet::from_ranges(almanac.map(&sources));
+                assert_eq!(RangeSet::from_ranges(composed.map(&sources)), by_chain);
+            }
+        }
+    }
+
+    #[test]
+    fn composed_map_solves_part_two() {
+        let almanac = Almanac::parse(SAMPLE);
+        let composed = almanac.compose();
+        let lowest = almanac.start_ranges.iter().flat_map(|range| composed.map(range)).map(|range| range.start).min();
+        assert_eq!(lowest, Some(46));
+    }
+
+    #[test]
+    fn composed_map_exports_almanac_format() {
+        let almanac = Almanac::parse(SAMPLE);
+        let exported = almanac.compose().export("seed", "location");
+        let reparsed = Almanac::parse(&format!("seeds: 0 1\n\n{exported}"));
+        for n in 0..200 {
+            assert_eq!(reparsed.source_to_dest(n), almanac.source_to_dest(n));
+        }
+    }
+
+    // small deterministic generator, so failures can be reproduced
+    // unsorted, possibly overlapping, touching or empty ranges within 0..64
+    fn ranges() -> impl Strategy<Value = Vec<SimpleRange>> {
+        prop::collection::vec((0..56i64, 0..9i64).prop_map(|(start, length)| SimpleRange{start, length}), 0..6)
+    }
+
+    fn oracle(ranges: &[SimpleRange]) -> BTreeSet<i64> {
+        ranges.iter().flat_map(|range| range.start..range.end()).collect()
+    }
+
+    fn elements(set: &RangeSet) -> BTreeSet<i64> {
+        oracle(&set.ranges)
+    }
+
+    // sorted, disjoint, non-touching and non-empty
+    fn is_normalised(set: &RangeSet) -> bool {
+        set.ranges.iter().all(|range| range.length > 0)
+            && set.ranges.windows(2).all(|pair| pair[0].end() < pair[1].start)
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(2000))]
+
+        #[test]
+        fn range_set_matches_btree_set(a_ranges in ranges(), b_ranges in ranges()) {
+            let (a, b) = (RangeSet::from_ranges(a_ranges.clone()), RangeSet::from_ranges(b_ranges.clone()));
+            let (a_oracle, b_oracle) = (oracle(&a_ranges), oracle(&b_ranges));
+
+            prop_assert!(is_normalised(&a) && is_normalised(&b), "{:?} {:?}", a, b);
+            prop_assert_eq!(elements(&a), a_oracle.clone());
+            prop_assert_eq!(a.len(), a_oracle.len() as i64);
+
+            let union = a.union(&b);
+            let intersection = a.intersection(&b);
+            let difference = a.difference(&b);
+            prop_assert!(is_normalised(&union) && is_normalised(&intersection) && is_normalised(&difference));
+            prop_assert_eq!(elements(&union), a_oracle.union(&b_oracle).copied().collect::<BTreeSet<i64>>());
+            prop_assert_eq!(elements(&intersection), a_oracle.intersection(&b_oracle).copied().collect::<BTreeSet<i64>>());
+            prop_assert_eq!(elements(&difference), a_oracle.difference(&b_oracle).copied().collect::<BTreeSet<i64>>());
+
+            for value in -2..66 {
+                prop_assert_eq!(a.contains(value), a_oracle.contains(&value), "{:?} contains {}", a, value);
+            }
+            for range in &b_ranges {
+                let expected = (range.start..range.end()).all(|value| a_oracle.contains(&value));
+                prop_assert_eq!(a.contains_range(range), expected, "{:?} contains {:?}", a, range);
+            }
         }
 
-        results
+        #[test]
+        fn range_set_laws(a_ranges in ranges(), b_ranges in ranges()) {
+            let (a, b) = (RangeSet::from_ranges(a_ranges), RangeSet::from_ranges(b_ranges));
+            prop_assert_eq!(a.difference(&b).union(&a.intersection(&b)), a.clone());
+            prop_assert_eq!(a.union(&b), b.union(&a));
+            prop_assert_eq!(a.intersection(&b), b.intersection(&a));
+            prop_assert_eq!(a.difference(&a), RangeSet::new());
+            prop_assert_eq!(a.union(&b).len(), a.len() + b.len() - a.intersection(&b).len());
+        }
+    }
+
+    #[test]
+    fn bijective_almanac_has_inverse() {
+        let almanac = Almanac::parse("seeds: 0 10\n\na-to-b map:\n5 0 5\n0 5 5\n");
+        let inverse = almanac.inverse().expect("swapped halves are a bijection");
+        for n in 0..12 {
+            assert_eq!(inverse.source_to_dest(almanac.source_to_dest(n)), n);
+        }
     }
 }
@@ -1,4 +1,4 @@
-use std::iter::zip;
+use std::{fmt, iter::zip};
 
 fn main() {
     let input = std::fs::read_to_string("day06/data/input.txt").expect("failed to read file");
@@ -10,11 +10,38 @@ fn main() {
     println!("multiple races: {races:?}");
     println!("single race: {race:?}");
 
-    let product = races.iter().map(Race::exceeding_button_times).product::<i64>();
-    let combinations = race.exceeding_button_times();
+    // None if the product does not fit
+    let product = races.iter().try_fold(1i128, |acc, race| acc.checked_mul(race.exceeding_button_times_exact()));
+    let combinations = race.exceeding_button_times_exact();
 
-    println!("product: {product}");
+    match product {
+        Some(product) => println!("product: {product}"),
+        None => println!("product: overflow")
+    }
     println!("combinations: {combinations}");
+
+    // the same races with other boats
+    let physics: [(&str, &dyn Physics); 4] = [
+        ("constant", &Constant),
+        ("acceleration 2", &Acceleration { per_ms: 2 }),
+        ("max speed 10", &MaxSpeed { limit: 10 }),
+        ("friction 1", &Friction { per_ms: 1 }),
+    ];
+    for (name, physics) in physics {
+        let counts = races.iter().map(|race| race.exceeding_button_times_with(physics)).collect::<Vec<i128>>();
+        println!("{name}: {counts:?}");
+    }
+
+    let huge = Race { duration: i64::MAX, record: i64::MAX };
+    println!("combinations for {huge:?}: {}", huge.exceeding_button_times_exact());
+    println!("first margins for {huge:?}: {:?}", huge.margins().take(3).collect::<Vec<(i64, i128)>>());
+
+    let report = Report::new(&races);
+    print!("{report}");
+
+    for race in &races {
+        println!("first margins for {race:?}: {:?}", race.margins().take(10).collect::<Vec<(i64, i128)>>());
+    }
 }
 
 #[derive(Debug)]
@@ -49,32 +76,352 @@ impl Race {
         }
     }
 
-    // can propably improve this with some sort of binary search
-    // 0 <= counter <= duration + 1
-    // but it is fast enough anyways, so there is no need to do that
-    // idea for binary search: define f(button_time) = 1 if exceeds record, else 0
-    // then over [0, duration+1] the function has three segments: left = 0, middle = 1, right = 0
-    // find two transititons with binsearch (keep in mind edge cases):
-    // 1. f(i) = 0, f(i+1) = 1
-    // 2. f(j) = 1, f(j+1) = 0
-    // then return ((i+1)..=j).len() = j - i - 1 + 1 = j - i
-    fn exceeding_button_times(&self) -> i64 {
-        let mut counter = 0;
-        for button_time in 0..=(self.duration+1) {
-            counter += if self.is_exceeding_button_time(button_time) {1} else {0};
+    // number of winning button times in O(1)
+    fn exceeding_button_times_exact(&self) -> i128 {
+        count_exceeding(self.duration as i128, self.record as i128)
+    }
+
+    // first and last winning button time for any physics in O(log duration):
+    // the distance has to rise and then fall over the button times, so a binary search finds the peak,
+    // and the winning button times are one interval around it whose ends are found by two more searches
+    fn winning_bounds(&self, physics: &dyn Physics) -> Option<(i64, i64)> {
+        if self.duration < 0 {
+            return None;
+        }
+
+        let distance = |button_time: i64| physics.distance(button_time, self.duration);
+
+        // first button time after which the distance does not increase anymore
+        let peak = first_true(0, self.duration, |t| t == self.duration || distance(t + 1) <= distance(t));
+        if distance(peak) <= self.record as i128 {
+            return None;
         }
-        counter
+
+        let first = first_true(0, peak, |t| distance(t) > self.record as i128);
+        let last = first_true(peak, self.duration, |t| t == self.duration || distance(t + 1) <= self.record as i128);
+        Some((first, last))
+    }
+
+    // i128 since every button time of a race lasting i64::MAX can win
+    fn exceeding_button_times_with(&self, physics: &dyn Physics) -> i128 {
+        self.winning_bounds(physics).map_or(0, |(first, last)| (last - first) as i128 + 1)
     }
 
-    fn is_exceeding_button_time(&self, button_time: i64) -> bool {
-        if button_time < 0 || button_time > self.duration {
-            return false;
+    // first and last winning button time, None if the record can not be beaten
+    fn winning_interval(&self) -> Option<(i64, i64)> {
+        exceeding_bounds(self.duration as i128, self.record as i128).map(|(lower, upper)| (lower as i64, upper as i64))
+    }
+
+    // how far the boat gets beyond the record, negative if it stays behind
+    fn margin(&self, button_time: i64) -> i128 {
+        Constant.distance(button_time, self.duration) - self.record as i128
+    }
+
+    // every winning button time with its margin, lazily since there can be up to i64::MAX of them
+    fn margins(&self) -> impl Iterator<Item = (i64, i128)> + '_ {
+        self.winning_interval().into_iter().flat_map(move |(first, last)| (first..=last).map(move |t| (t, self.margin(t))))
+    }
+}
+
+// smallest t in [lo, hi] with f(t), f has to be false and then true, hi if there is none
+fn first_true(mut lo: i64, mut hi: i64, f: impl Fn(i64) -> bool) -> i64 {
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if f(mid) {
+            hi = mid;
+        } else {
+            lo = mid + 1;
         }
+    }
+    lo
+}
+
+// distance a boat travels in a race, given how long the button was held
+trait Physics {
+    fn distance(&self, button_time: i64, duration: i64) -> i128;
+}
+
+// the puzzle's boat: speed is the button time, then constant speed for the rest
+struct Constant;
+
+// every millisecond of holding the button adds per_ms to the speed
+struct Acceleration {
+    per_ms: i64
+}
+
+// like Constant, but the speed never exceeds the limit
+struct MaxSpeed {
+    limit: i64
+}
+
+// like Constant, but the speed drops by per_ms every millisecond of moving until the boat stops
+struct Friction {
+    per_ms: i64
+}
+
+impl Physics for Constant {
+    fn distance(&self, button_time: i64, duration: i64) -> i128 {
+        button_time as i128 * (duration - button_time) as i128
+    }
+}
+
+impl Physics for Acceleration {
+    fn distance(&self, button_time: i64, duration: i64) -> i128 {
+        self.per_ms as i128 * button_time as i128 * (duration - button_time) as i128
+    }
+}
+
+impl Physics for MaxSpeed {
+    fn distance(&self, button_time: i64, duration: i64) -> i128 {
+        button_time.min(self.limit) as i128 * (duration - button_time) as i128
+    }
+}
+
+impl Physics for Friction {
+    fn distance(&self, button_time: i64, duration: i64) -> i128 {
+        let speed = button_time as i128;
+        let friction = self.per_ms.max(1) as i128;
+
+        // milliseconds in which the boat still moves, then sum up speed - friction * k
+        let moving = ((duration - button_time) as i128).min((speed + friction - 1) / friction);
+        moving * speed - friction * moving * (moving - 1) / 2
+    }
+}
+
+// number of integers t in [0, duration] with t * (duration - t) > record
+fn count_exceeding(duration: i128, record: i128) -> i128 {
+    exceeding_bounds(duration, record).map_or(0, |(lower, upper)| upper - lower + 1)
+}
+
+// first and last t in [0, duration] with t * (duration - t) > record
+// the winning t lie strictly between the roots of t^2 - duration * t + record,
+// which are (duration -+ sqrt(duration^2 - 4 * record)) / 2,
+// the integer square root only gives an estimate of the lower bound which is then corrected,
+// exact as long as duration^2 fits into an i128, so for every duration and record of an i64
+fn exceeding_bounds(duration: i128, record: i128) -> Option<(i128, i128)> {
+    if duration < 0 {
+        return None;
+    }
+
+    let wins = |t: i128| t * (duration - t) > record;
+    let discriminant = duration * duration - 4 * record;
+    if discriminant <= 0 {
+        return None;
+    }
 
-        let speed = button_time;
-        let remaining_time = self.duration - button_time;
-        let distance = speed * remaining_time;
+    let root = discriminant.isqrt();
+    let mut lower = ((duration - root) / 2).clamp(0, duration);
+    while lower <= duration / 2 && !wins(lower) {
+        lower += 1;
+    }
+    while lower > 0 && wins(lower - 1) {
+        lower -= 1;
+    }
+
+    // the winning times are symmetric around duration / 2
+    let upper = duration - lower;
+    if lower > upper || !wins(lower) {
+        return None;
+    }
+
+    Some((lower, upper))
+}
+
+#[derive(Debug)]
+struct RaceReport {
+    duration: i64,
+    record: i64,
+    interval: Option<(i64, i64)>, // first and last winning button time
+    count: i128,                  // number of winning button times
+    best_margin: Option<i128>     // margin of the best button time
+}
+
+#[derive(Debug)]
+struct Report {
+    races: Vec<RaceReport>,
+    unwinnable: Vec<usize>, // indices of the races which can never be won
+    product: Option<i128>   // product of all counts, as in the puzzle, None if it does not fit
+}
+
+impl Report {
+    fn new(races: &[Race]) -> Report {
+        let mut report = Report { races: vec![], unwinnable: vec![], product: Some(1) };
+
+        for (index, race) in races.iter().enumerate() {
+            let interval = race.winning_interval();
+            let count = interval.map_or(0, |(first, last)| (last - first) as i128 + 1);
+
+            // the distance is largest in the middle of the race
+            let best_margin = interval.map(|_| race.margin(race.duration / 2));
+
+            if interval.is_none() {
+                report.unwinnable.push(index);
+            }
+            report.product = report.product.and_then(|product| product.checked_mul(count));
+            report.races.push(RaceReport { duration: race.duration, record: race.record, interval, count, best_margin });
+        }
+
+        report
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, race) in self.races.iter().enumerate() {
+            write!(f, "race {index}: duration {}, record {}: ", race.duration, race.record)?;
+            match (race.interval, race.best_margin) {
+                (Some((first, last)), Some(margin)) => writeln!(f, "win with [{first}, {last}], {} ways, best margin {margin}", race.count)?,
+                _ => writeln!(f, "can not be won")?,
+            }
+        }
+
+        if !self.unwinnable.is_empty() {
+            writeln!(f, "unwinnable races: {:?}", self.unwinnable)?;
+        }
+        match self.product {
+            Some(product) => writeln!(f, "product: {product}"),
+            None => writeln!(f, "product: overflow")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // winning button times of a race by trying every one of them
+    fn count_by_scan(race: &Race, physics: &dyn Physics) -> i128 {
+        (0..=race.duration).filter(|&t| physics.distance(t, race.duration) > race.record as i128).count() as i128
+    }
+
+    #[test]
+    fn closed_form_matches_scan() {
+        for duration in -3..60 {
+            for record in -40..(duration * duration / 4 + 3) {
+                let race = Race { duration, record };
+                assert_eq!(race.exceeding_button_times_exact(), count_by_scan(&race, &Constant), "{race:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn record_on_the_peak_can_not_be_beaten() {
+        // d * d == 4 * record: the best button time only ties the record
+        for duration in (0..100).step_by(2) {
+            let race = Race { duration, record: duration * duration / 4 };
+            assert_eq!(race.exceeding_button_times_exact(), 0, "{race:?}");
+            assert_eq!(race.winning_interval(), None);
+
+            let race = Race { duration, record: duration * duration / 4 - 1 };
+            assert_eq!(race.exceeding_button_times_exact(), 1, "{race:?}");
+        }
+    }
+
+    #[test]
+    fn negative_records_are_beaten_by_every_button_time() {
+        for record in [-1, -7, -1000, i64::MIN] {
+            let race = Race { duration: 30, record };
+            assert_eq!(race.exceeding_button_times_exact(), 31);
+            assert_eq!(race.winning_interval(), Some((0, 30)));
+        }
+    }
+
+    #[test]
+    fn binary_search_matches_scan() {
+        let physics: [&dyn Physics; 7] = [
+            &Constant,
+            &Acceleration { per_ms: 1 },
+            &Acceleration { per_ms: 3 },
+            &MaxSpeed { limit: 0 },
+            &MaxSpeed { limit: 7 },
+            &Friction { per_ms: 1 },
+            &Friction { per_ms: 4 },
+        ];
+
+        for physics in physics {
+            for duration in -2..45 {
+                for record in -5..(duration * duration / 2 + 3) {
+                    let race = Race { duration, record };
+                    assert_eq!(race.exceeding_button_times_with(physics), count_by_scan(&race, physics), "{race:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn binary_search_agrees_with_closed_form_for_large_races() {
+        for (duration, record) in [(i64::MAX, i64::MAX), (1 << 40, 1 << 60), (i64::MAX, -1), (1 << 31, (1 << 60) - 1)] {
+            let race = Race { duration, record };
+            assert_eq!(race.exceeding_button_times_with(&Constant), race.exceeding_button_times_exact(), "{race:?}");
+        }
+    }
+
+    #[test]
+    fn report_on_sample() {
+        let races = Race::parse_multiple("7  15   30  4", "9  40  200  4");
+        let report = Report::new(&races);
+
+        // the last race peaks at 2 * 2 = 4, which only ties the record
+        assert_eq!(report.unwinnable, vec![3]);
+        assert_eq!(report.product, Some(0));
+        assert_eq!(Report::new(&races[..3]).product, Some(288));
+
+        let first = &report.races[0];
+        assert_eq!((first.interval, first.count, first.best_margin), (Some((2, 5)), 4, Some(3)));
+        assert_eq!(report.races[3].best_margin, None);
+
+        // every count is i64::MAX - 1, two of them still fit into an i128, three do not
+        let huge = || Race { duration: i64::MAX, record: 0 };
+        assert_eq!(Report::new(&[huge(), huge()]).product, Some((i64::MAX as i128 - 1) * (i64::MAX as i128 - 1)));
+        let report = Report::new(&[huge(), huge(), huge()]);
+        assert_eq!(report.product, None);
+        assert!(report.to_string().ends_with("product: overflow\n"));
+    }
+
+    #[test]
+    fn margins_of_winning_button_times() {
+        let race = Race { duration: 7, record: 9 };
+        assert_eq!(race.margins().collect::<Vec<(i64, i128)>>(), vec![(2, 1), (3, 3), (4, 3), (5, 1)]);
+        assert_eq!(Race { duration: 4, record: 4 }.margins().next(), None);
+    }
+
+    #[test]
+    fn margins_are_lazy() {
+        let huge = Race { duration: i64::MAX, record: -1 };
+        let mut margins = huge.margins();
+        assert_eq!(margins.next(), Some((0, 1)));
+        assert_eq!(margins.next(), Some((1, i64::MAX as i128)));
+    }
+
+    #[test]
+    fn sample_races() {
+        let races = Race::parse_multiple("7  15   30", "9  40  200");
+        let counts = races.iter().map(Race::exceeding_button_times_exact).collect::<Vec<i128>>();
+        assert_eq!(counts, vec![4, 8, 9]);
+        assert_eq!(Race::parse_single("7  15   30", "9  40  200").exceeding_button_times_exact(), 71503);
+    }
+
+    #[test]
+    fn extreme_races() {
+        let wins = |duration: i128, record: i128, t: i128| t * (duration - t) > record;
+
+        for (duration, record) in [(i64::MAX, i64::MAX), (i64::MAX, 0), (i64::MAX, -1), (i64::MAX - 1, i64::MAX), (3, i64::MAX)] {
+            let (d, r) = (duration as i128, record as i128);
+            let count = Race { duration, record }.exceeding_button_times_exact();
+            match exceeding_bounds(d, r) {
+                Some((lower, upper)) => {
+                    assert!(wins(d, r, lower) && wins(d, r, upper));
+                    assert!(lower == 0 || !wins(d, r, lower - 1));
+                    assert!(upper == d || !wins(d, r, upper + 1));
+                    assert_eq!(count, upper - lower + 1);
+                },
+                None => assert_eq!(count, 0),
+            }
+        }
 
-        distance > self.record
+        // with record 0 every button time but holding it for none or all of the race wins
+        assert_eq!(count_exceeding(i64::MAX as i128, 0), i64::MAX as i128 - 1);
+        assert_eq!(count_exceeding(i64::MAX as i128, -1), i64::MAX as i128 + 1);
+        assert_eq!(count_exceeding(3, i64::MAX as i128), 0);
     }
 }
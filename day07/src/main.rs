@@ -1,200 +1,317 @@
-use std::{cmp::Ordering, collections::HashMap};
+use std::fmt;
 
 fn main() {
     let input = std::fs::read_to_string("day07/data/input.txt").expect("failed to read file");
+
+    // every hand has as many cards as the first one
+    let size = input.split_whitespace().next().map_or(5, |cards_str| cards_str.chars().count());
     let mut hands = vec![];
-    for line in input.lines() {
+    for (n, line) in input.lines().enumerate() {
         let mut parts = line.split_whitespace();
-        let cards_str = parts.next().expect("failed to read hand cards");
-        let bid_str = parts.next().expect("failed to read hand bid");
-        let hand = Hand::parse(cards_str, bid_str);
-        hands.push(hand);
+        let cards_str = parts.next().unwrap_or("");
+        let bid_str = parts.next().unwrap_or("");
+        match Hand::parse(cards_str, bid_str, size) {
+            Ok(hand) => hands.push(hand),
+            Err(e) => panic!("line {}: {e}", n + 1),
+        }
     }
 
-    hands.sort_by(Hand::cmp);
-    let mut winnings = 0;
-    for (i, hand) in hands.iter().enumerate() {
-        let rank = (i + 1) as i32;
-        winnings += hand.bid * rank;
-    }
+    let standard = RuleSet::standard();
+    let winnings = total_winnings(&mut hands, &standard);
     println!("winnings: {winnings}");
 
-    hands.sort_by(Hand::cmp2);
-    let mut winnings2 = 0;
-    for (i, hand) in hands.iter().enumerate() {
-        let rank = (i + 1) as i32;
-        winnings2 += hand.bid * rank;
-    }
+    let jokers = RuleSet::jokers();
+    let winnings2 = total_winnings(&mut hands, &jokers);
     println!("winnings2: {winnings2}");
+
+    // optionally the same winnings with a radix sort over the keys
+    let args = std::env::args().collect::<Vec<String>>();
+    if args.iter().any(|arg| arg == "--radix") {
+        println!("winnings by radix sort: {}", radix_winnings(&hands, &standard, size));
+    }
+
+    // variants are only configuration
+    let twos_wild = RuleSet { wild: vec![Card::J, Card::Two], ..RuleSet::jokers() };
+    println!("winnings with jokers and twos wild: {}", total_winnings(&mut hands, &twos_wild));
+
+    let untyped = RuleSet { typed: false, ..RuleSet::standard() };
+    println!("winnings by card order only: {}", total_winnings(&mut hands, &untyped));
+
+    // audit trail of every hand, printed as csv with --csv
+    let report = Report::new(&mut hands, &standard);
+    let report2 = Report::new(&mut hands, &jokers);
+    println!("report totals: standard {}, jokers {}", report.total(), report2.total());
+
+    if args.iter().any(|arg| arg == "--csv") {
+        print!("{}", report.csv("standard"));
+        print!("{}", report2.csv("jokers").lines().skip(1).map(|line| format!("{line}\n")).collect::<String>());
+    }
+
+    // optional file with suited poker hands like "AsKsQsJsTs 100"
+    if let Some(path) = args.iter().position(|arg| arg == "--poker").and_then(|i| args.get(i + 1)) {
+        let input = std::fs::read_to_string(path).expect("failed to read poker file");
+        let mut poker_hands = vec![];
+        for (n, line) in input.lines().enumerate() {
+            let mut parts = line.split_whitespace();
+            let cards_str = parts.next().unwrap_or("");
+            let bid_str = parts.next().unwrap_or("");
+            match PokerHand::parse(cards_str, bid_str) {
+                Ok(hand) => poker_hands.push(hand),
+                Err(e) => panic!("poker line {}: {e}", n + 1),
+            }
+        }
+
+        let poker_rules = PokerRules { ace_low: true };
+        let poker_winnings = total_winnings(&mut poker_hands, &poker_rules);
+        for hand in &poker_hands {
+            println!("bid {}: {:?}", hand.bid, poker_rules.category(&hand.cards));
+        }
+        println!("poker winnings: {poker_winnings}");
+    }
 }
 
-#[derive(Debug)]
-enum HandType {
-    FiveKind,
-    FourKind,
-    FullHouse,
-    ThreeKind,
-    TwoPair,
-    OnePair,
-    HighCard
+// ordering shared by camel cards and poker: larger keys win under the given rules
+trait Ranked {
+    type Rules;
+
+    fn key(&self, rules: &Self::Rules) -> u128;
+    fn bid(&self) -> i32;
 }
 
-impl HandType {
-    fn value(&self) -> u8 {
-        match &self {
-            HandType::FiveKind  => 7,
-            HandType::FourKind  => 6,
-            HandType::FullHouse => 5,
-            HandType::ThreeKind => 4,
-            HandType::TwoPair   => 3,
-            HandType::OnePair   => 2,
-            HandType::HighCard  => 1
+#[derive(Debug, PartialEq)]
+enum ParseError {
+    UnknownCard { position: usize, found: char },
+    UnknownSuit { position: usize, found: char },
+    WrongSize { expected: usize, found: usize },
+    TooLarge { size: usize, max: usize },
+    DuplicateCard { position: usize, found: String },
+    InvalidBid(String)
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnknownCard { position, found } => write!(f, "unknown card '{found}' at position {position}"),
+            ParseError::UnknownSuit { position, found } => write!(f, "unknown suit '{found}' at position {position}"),
+            ParseError::WrongSize { expected, found } => write!(f, "expected {expected} cards, found {found}"),
+            ParseError::TooLarge { size, max } => write!(f, "hands of {size} cards are larger than the maximum of {max}"),
+            ParseError::DuplicateCard { position, found } => write!(f, "duplicate card '{found}' at position {position}"),
+            ParseError::InvalidBid(bid) => write!(f, "invalid bid '{bid}'"),
         }
     }
+}
+
+// the key packs 4 bits per entry of the type and per card
+const MAX_HAND_SIZE: usize = 15;
+
+fn total_winnings<H: Ranked>(hands: &mut [H], rules: &H::Rules) -> i32 {
+    hands.sort_by_cached_key(|hand| hand.key(rules));
 
-    fn cmp(&self, other: &HandType) -> Ordering {
-        self.value().cmp(&other.value())
+    let mut winnings = 0;
+    for (i, hand) in hands.iter().enumerate() {
+        let rank = (i + 1) as i32;
+        winnings += hand.bid() * rank;
+    }
+    winnings
+}
+
+// sizes of the groups of equal cards from largest to smallest,
+// hands of the same size compare by these sizes one after another
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct HandType(Vec<usize>);
+
+impl HandType {
+    fn name(&self) -> String {
+        let groups = &self.0;
+        let pairs = groups.iter().filter(|g| **g == 2).count();
+        let rest_single = groups.iter().skip(1).all(|g| *g == 1);
+
+        match groups[0] {
+            0 | 1 => "high card".to_string(),
+            2 if pairs == 1 => "one pair".to_string(),
+            2 if pairs == 2 && groups.iter().skip(2).all(|g| *g == 1) => "two pair".to_string(),
+            3 if groups.len() == 2 && groups[1] == 2 => "full house".to_string(),
+            n if rest_single => format!("{n} of a kind"),
+            _ => groups.iter().map(|g| g.to_string()).collect::<Vec<String>>().join("+")
+        }
     }
 }
 
 #[derive(Debug)]
 struct Hand {
-    cards: [Card;5],
+    cards: Vec<Card>,
     bid: i32
 }
 
-// idea for fast comparison: map every hand to a single number (maybe radix sort useful too?)
 impl Hand {
-    fn new() -> Hand {
-        Hand {
-            cards: [Card::A; 5],
-            bid: 0
+    fn parse(cards_str: &str, bid_str: &str, size: usize) -> Result<Hand, ParseError> {
+        if size > MAX_HAND_SIZE {
+            return Err(ParseError::TooLarge { size, max: MAX_HAND_SIZE });
         }
-    }
 
-    fn parse(cards_str: &str, bid_str: &str) -> Hand {
-        let mut hand = Hand::new();
+        let mut cards = vec![];
+
+        for (position, c) in cards_str.chars().enumerate() {
+            cards.push(Card::parse(c).ok_or(ParseError::UnknownCard { position, found: c })?);
+        }
 
-        for (i, c) in cards_str.chars().enumerate() {
-            hand.cards[i] = Card::parse(c);
+        if cards.len() != size {
+            return Err(ParseError::WrongSize { expected: size, found: cards.len() });
         }
 
-        hand.bid = bid_str.parse().expect("failed to parse bid");
-        hand
+        let bid = bid_str.parse().map_err(|_| ParseError::InvalidBid(bid_str.to_string()))?;
+        Ok(Hand { cards, bid })
     }
+}
+
+// every hand maps to a single number: the type with 4 bits per group, padded to one group per card,
+// then 4 bits per card, so comparing keys is the same as comparing hands of the same size
+impl Ranked for Hand {
+    type Rules = RuleSet;
 
-    fn hand_type(&self) -> HandType {
-        let mut kinds = HashMap::new();
+    fn key(&self, rules: &RuleSet) -> u128 {
+        let mut key = 0;
+        if rules.typed {
+            let hand_type = rules.hand_type(&self.cards);
+            for i in 0..self.cards.len() {
+                key = key << 4 | hand_type.0.get(i).copied().unwrap_or(0) as u128;
+            }
+        }
 
         for card in &self.cards {
-            *kinds.entry(card).or_insert(0) += 1;
+            key = key << 4 | rules.rank(*card) as u128;
         }
+        key
+    }
 
-        let mut cardinalities = kinds.values().map(|x| *x).collect::<Vec::<i32>>();
-        cardinalities.sort();
-        let card_str = cardinalities.iter().fold(String::new(), |mut acc, c| {acc.push(char::from_digit(*c as u32, 10).unwrap()); acc});
+    fn bid(&self) -> i32 {
+        self.bid
+    }
+}
 
-        match card_str.as_str() {
-            "5" => HandType::FiveKind,
-            "14" => HandType::FourKind,
-            "23" => HandType::FullHouse,
-            "113" => HandType::ThreeKind,
-            "122" => HandType::TwoPair,
-            "1112" => HandType::OnePair,
-            "11111" => HandType::HighCard,
-            _ => panic!("failed to parse hand type")
+// everything which differs between variants of the game
+#[derive(Debug, Clone)]
+struct RuleSet {
+    order: Vec<Card>, // from weakest to strongest
+    wild: Vec<Card>,  // cards which join the largest group of the other cards
+    typed: bool       // whether the hand type counts before the card order
+}
+
+impl RuleSet {
+    fn standard() -> RuleSet {
+        RuleSet {
+            order: Card::ALL.iter().rev().copied().collect(),
+            wild: vec![],
+            typed: true
         }
     }
 
-    fn cmp(&self, other: &Hand) -> Ordering {
-        let cmp_type = self.hand_type().cmp(&other.hand_type());
-        if !cmp_type.is_eq() {
-            return cmp_type;
+    // jokers are wild, but weaker than any other card
+    fn jokers() -> RuleSet {
+        let mut order = vec![Card::J];
+        order.extend(Card::ALL.iter().rev().filter(|card| **card != Card::J));
+
+        RuleSet {
+            order,
+            wild: vec![Card::J],
+            typed: true
         }
+    }
 
-        for i in 0..5 {
-            let cmp_card = self.cards[i].cmp(&other.cards[i]);
-            if !cmp_card.is_eq() {
-                return cmp_card;
+    // 1 for the weakest card, 0 for cards without a rank
+    fn rank(&self, card: Card) -> u8 {
+        self.order.iter().position(|c| *c == card).map_or(0, |p| p as u8 + 1)
+    }
+
+    // wild cards always do best by joining the largest group
+    fn hand_type(&self, cards: &[Card]) -> HandType {
+        let mut kinds = [0; 15];
+        let mut wilds = 0;
+
+        for card in cards {
+            if self.wild.contains(card) {
+                wilds += 1;
+            } else {
+                kinds[card.value() as usize] += 1;
             }
         }
 
-        Ordering::Equal
+        let mut groups = kinds.into_iter().filter(|k| *k > 0).collect::<Vec<usize>>();
+        groups.sort_by(|a, b| b.cmp(a));
+        if groups.is_empty() {
+            groups.push(0);
+        }
+        groups[0] += wilds;
+
+        HandType(groups)
     }
 
-    fn hand_type2(&self) -> HandType {
-        let mut kinds = HashMap::new();
+    // the card all wild cards of the hand stand for, the strongest one of the largest group
+    fn substitution(&self, cards: &[Card]) -> Option<Card> {
+        if !cards.iter().any(|card| self.wild.contains(card)) {
+            return None;
+        }
 
-        for card in &self.cards {
-            *kinds.entry(card).or_insert(0) += 1;
-        }
-
-        let mut cardinalities = kinds.values().map(|x| *x).collect::<Vec::<i32>>();
-        cardinalities.sort();
-        let card_str = cardinalities.iter().fold(String::new(), |mut acc, c| {acc.push(char::from_digit(*c as u32, 10).unwrap()); acc});
-        let jokers = *kinds.get(&Card::J).unwrap_or(&0);
-
-        match card_str.as_str() {
-            "5" => HandType::FiveKind,
-            "14" => {
-                match jokers {
-                    1 => HandType::FiveKind,
-                    4 => HandType::FiveKind,
-                    _ => HandType::FourKind
-                }
-            },
-            "23" => {
-                match jokers {
-                    2 => HandType::FiveKind,
-                    3 => HandType::FiveKind,
-                    _ => HandType::FullHouse
-                }
+        let mut best: Option<(usize, u8, Card)> = None;
+        for card in cards.iter().filter(|card| !self.wild.contains(card)) {
+            let count = cards.iter().filter(|c| *c == card).count();
+            let candidate = (count, self.rank(*card), *card);
+            if best.is_none_or(|b| (candidate.0, candidate.1) > (b.0, b.1)) {
+                best = Some(candidate);
             }
-            "113" => {
-                match jokers {
-                    1 => HandType::FourKind,
-                    3 => HandType::FourKind,
-                    _ => HandType::ThreeKind
-                }
-            },
-            "122" => {
-                match jokers {
-                    1 => HandType::FullHouse,
-                    2 => HandType::FourKind,
-                    _ => HandType::TwoPair,
-                }
-            },
-            "1112" => {
-                match jokers {
-                    1 => HandType::ThreeKind,
-                    2 => HandType::ThreeKind,
-                    _ => HandType::OnePair,
-                }
-            },
-            "11111" => {
-                match jokers {
-                    1 => HandType::OnePair,
-                    _ => HandType::HighCard
-                }
-            },
-            _ => panic!("failed to parse hand type")
-        }
-    }
-
-    fn cmp2(&self, other: &Hand) -> Ordering {
-        let cmp_type = self.hand_type2().cmp(&other.hand_type2());
-        if !cmp_type.is_eq() {
-            return cmp_type;
         }
 
-        for i in 0..5 {
-            let cmp_card = self.cards[i].cmp2(&other.cards[i]);
-            if !cmp_card.is_eq() {
-                return cmp_card;
-            }
+        // a hand of only wild cards becomes the strongest other card
+        best.map(|b| b.2).or(self.order.iter().rev().find(|card| !self.wild.contains(card)).copied())
+    }
+}
+
+#[derive(Debug)]
+struct ReportRow {
+    cards: String,
+    hand_type: HandType,
+    substitution: Option<Card>,
+    rank: usize,
+    bid: i32,
+    winnings: i32
+}
+
+// every hand in order of its rank under one rule set
+#[derive(Debug)]
+struct Report {
+    rows: Vec<ReportRow>
+}
+
+impl Report {
+    fn new(hands: &mut [Hand], rules: &RuleSet) -> Report {
+        hands.sort_by_cached_key(|hand| hand.key(rules));
+
+        let rows = hands.iter().enumerate().map(|(i, hand)| ReportRow {
+            cards: hand.cards.iter().map(Card::char).collect(),
+            hand_type: rules.hand_type(&hand.cards),
+            substitution: rules.substitution(&hand.cards),
+            rank: i + 1,
+            bid: hand.bid,
+            winnings: hand.bid * (i + 1) as i32
+        }).collect();
+
+        Report { rows }
+    }
+
+    fn total(&self) -> i32 {
+        self.rows.iter().map(|row| row.winnings).sum()
+    }
+
+    // none of the fields can contain a comma, so nothing needs quoting
+    fn csv(&self, rules_name: &str) -> String {
+        let mut out = String::from("rules,cards,type,substitution,rank,bid,winnings\n");
+
+        for row in &self.rows {
+            let substitution = row.substitution.map(|card| card.char().to_string()).unwrap_or_default();
+            out.push_str(&format!("{rules_name},{},{},{substitution},{},{},{}\n", row.cards, row.hand_type.name(), row.rank, row.bid, row.winnings));
         }
 
-        Ordering::Equal
+        out
     }
 }
 
@@ -216,22 +333,46 @@ enum Card {
 }
 
 impl Card {
-    fn parse(c: char) -> Card {
+    // from strongest to weakest, as in the puzzle
+    const ALL: [Card; 13] = [
+        Card::A, Card::K, Card::Q, Card::J, Card::T, Card::Nine, Card::Eight,
+        Card::Seven, Card::Six, Card::Five, Card::Four, Card::Three, Card::Two
+    ];
+
+    fn char(&self) -> char {
+        match self {
+            Card::A     => 'A',
+            Card::K     => 'K',
+            Card::Q     => 'Q',
+            Card::J     => 'J',
+            Card::T     => 'T',
+            Card::Nine  => '9',
+            Card::Eight => '8',
+            Card::Seven => '7',
+            Card::Six   => '6',
+            Card::Five  => '5',
+            Card::Four  => '4',
+            Card::Three => '3',
+            Card::Two   => '2'
+        }
+    }
+
+    fn parse(c: char) -> Option<Card> {
         match c {
-            'A' => Card::A,
-            'K' => Card::K,
-            'Q' => Card::Q,
-            'J' => Card::J,
-            'T' => Card::T,
-            '9' => Card::Nine,
-            '8' => Card::Eight,
-            '7' => Card::Seven,
-            '6' => Card::Six,
-            '5' => Card::Five,
-            '4' => Card::Four,
-            '3' => Card::Three,
-            '2' => Card::Two,
-            _ => panic!("failed card parse")
+            'A' => Some(Card::A),
+            'K' => Some(Card::K),
+            'Q' => Some(Card::Q),
+            'J' => Some(Card::J),
+            'T' => Some(Card::T),
+            '9' => Some(Card::Nine),
+            '8' => Some(Card::Eight),
+            '7' => Some(Card::Seven),
+            '6' => Some(Card::Six),
+            '5' => Some(Card::Five),
+            '4' => Some(Card::Four),
+            '3' => Some(Card::Three),
+            '2' => Some(Card::Two),
+            _ => None
         }
     }
 
@@ -252,30 +393,353 @@ impl Card {
             Card::Two   => 2
         }
     }
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+enum Suit {
+    Clubs,
+    Diamonds,
+    Hearts,
+    Spades
+}
+
+impl Suit {
+    fn parse(c: char) -> Option<Suit> {
+        match c {
+            'c' => Some(Suit::Clubs),
+            'd' => Some(Suit::Diamonds),
+            'h' => Some(Suit::Hearts),
+            's' => Some(Suit::Spades),
+            _ => None
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+struct SuitedCard {
+    card: Card,
+    suit: Suit
+}
+
+#[derive(Debug)]
+struct PokerHand {
+    cards: [SuitedCard; 5],
+    bid: i32
+}
 
-    fn value2(&self) -> u8 {
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum PokerCategory {
+    StraightFlush,
+    FourKind,
+    FullHouse,
+    Flush,
+    Straight,
+    ThreeKind,
+    TwoPair,
+    OnePair,
+    HighCard
+}
+
+impl PokerCategory {
+    fn value(&self) -> u8 {
         match &self {
-            Card::A     => 14,
-            Card::K     => 13,
-            Card::Q     => 12,
-            Card::J     => 0,
-            Card::T     => 10,
-            Card::Nine  => 9,
-            Card::Eight => 8,
-            Card::Seven => 7,
-            Card::Six   => 6,
-            Card::Five  => 5,
-            Card::Four  => 4,
-            Card::Three => 3,
-            Card::Two   => 2
+            PokerCategory::StraightFlush => 9,
+            PokerCategory::FourKind      => 8,
+            PokerCategory::FullHouse     => 7,
+            PokerCategory::Flush         => 6,
+            PokerCategory::Straight      => 5,
+            PokerCategory::ThreeKind     => 4,
+            PokerCategory::TwoPair       => 3,
+            PokerCategory::OnePair       => 2,
+            PokerCategory::HighCard      => 1
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PokerRules {
+    ace_low: bool // whether A-2-3-4-5 counts as the lowest straight
+}
+
+impl PokerHand {
+    // every card is a rank followed by a suit, e.g. "Ah"
+    fn parse(cards_str: &str, bid_str: &str) -> Result<PokerHand, ParseError> {
+        let chars = cards_str.chars().collect::<Vec<char>>();
+        if chars.len() != 10 {
+            return Err(ParseError::WrongSize { expected: 5, found: chars.len().div_ceil(2) });
+        }
+
+        let mut cards = [SuitedCard { card: Card::A, suit: Suit::Spades }; 5];
+        for (i, pair) in chars.chunks(2).enumerate() {
+            cards[i] = SuitedCard {
+                card: Card::parse(pair[0]).ok_or(ParseError::UnknownCard { position: 2 * i, found: pair[0] })?,
+                suit: Suit::parse(pair[1]).ok_or(ParseError::UnknownSuit { position: 2 * i + 1, found: pair[1] })?
+            };
+
+            // a single deck holds every card once
+            if cards[..i].contains(&cards[i]) {
+                return Err(ParseError::DuplicateCard { position: 2 * i, found: pair.iter().collect() });
+            }
+        }
+
+        let bid = bid_str.parse().map_err(|_| ParseError::InvalidBid(bid_str.to_string()))?;
+        Ok(PokerHand { cards, bid })
+    }
+}
+
+impl PokerRules {
+    // card values from most to least important for breaking ties:
+    // larger groups first, then higher cards, straights only by their top card
+    fn tiebreak(&self, cards: &[SuitedCard]) -> Vec<u8> {
+        let mut kinds = [0; 15];
+        for card in cards {
+            kinds[card.card.value() as usize] += 1;
+        }
+
+        let mut groups = (2..15).filter(|v| kinds[*v] > 0).map(|v| (kinds[v], v as u8)).collect::<Vec<(usize, u8)>>();
+        groups.sort_by(|a, b| b.cmp(a));
+
+        let values = groups.iter().map(|(_, v)| *v).collect::<Vec<u8>>();
+        if let Some(top) = self.straight_top(&values) {
+            return vec![top];
+        }
+
+        groups.iter().flat_map(|(count, v)| std::iter::repeat_n(*v, *count)).collect()
+    }
+
+    // highest card of a straight, values have to be distinct and sorted from high to low
+    fn straight_top(&self, values: &[u8]) -> Option<u8> {
+        if values.len() != 5 {
+            return None;
+        }
+
+        if values[0] - values[4] == 4 {
+            return Some(values[0]);
+        }
+
+        // the ace plays as a one below the two
+        if self.ace_low && values == [14, 5, 4, 3, 2] {
+            return Some(5);
+        }
+
+        None
+    }
+
+    fn category(&self, cards: &[SuitedCard]) -> PokerCategory {
+        let mut kinds = [0; 15];
+        for card in cards {
+            kinds[card.card.value() as usize] += 1;
+        }
+
+        let values = (2..15).rev().filter(|v| kinds[*v] > 0).map(|v| v as u8).collect::<Vec<u8>>();
+        let straight = self.straight_top(&values).is_some();
+        let flush = cards.iter().all(|card| card.suit == cards[0].suit);
+
+        let mut groups = kinds.into_iter().filter(|k| *k > 0).collect::<Vec<usize>>();
+        groups.sort_by(|a, b| b.cmp(a));
+
+        match (straight, flush, groups[0], groups.get(1).copied().unwrap_or(0)) {
+            (true, true, _, _) => PokerCategory::StraightFlush,
+            (_, _, 4, _) => PokerCategory::FourKind,
+            (_, _, 3, 2) => PokerCategory::FullHouse,
+            (_, true, _, _) => PokerCategory::Flush,
+            (true, _, _, _) => PokerCategory::Straight,
+            (_, _, 3, _) => PokerCategory::ThreeKind,
+            (_, _, 2, 2) => PokerCategory::TwoPair,
+            (_, _, 2, _) => PokerCategory::OnePair,
+            _ => PokerCategory::HighCard
+        }
+    }
+}
+
+// category in bits 20..23, then the tiebreak values with 4 bits each
+impl Ranked for PokerHand {
+    type Rules = PokerRules;
+
+    fn key(&self, rules: &PokerRules) -> u128 {
+        let mut key = rules.category(&self.cards).value() as u128;
+        let tiebreak = rules.tiebreak(&self.cards);
+
+        for i in 0..5 {
+            key = key << 4 | tiebreak.get(i).copied().unwrap_or(0) as u128;
+        }
+        key
+    }
+
+    fn bid(&self) -> i32 {
+        self.bid
+    }
+}
+
+// winnings with the hands ordered by a radix sort over their keys instead of a comparison sort
+fn radix_winnings(hands: &[Hand], rules: &RuleSet, size: usize) -> i32 {
+    let mut keys = hands.iter().map(|hand| (hand.key(rules), hand.bid)).collect::<Vec<(u128, i32)>>();
+    radix_sort(&mut keys, size);
+    keys.iter().enumerate().map(|(i, (_, bid))| bid * (i + 1) as i32).sum()
+}
+
+// least significant digit radix sort over the lowest bytes of the hand keys, one byte per pass
+fn radix_sort<T: Copy>(items: &mut Vec<(u128, T)>, bytes: usize) {
+    if items.is_empty() {
+        return;
+    }
+
+    let mut buffer = Vec::with_capacity(items.len());
+
+    for shift in (0..bytes).map(|byte| byte * 8) {
+        let mut counts = [0usize; 257];
+        for (key, _) in items.iter() {
+            counts[((key >> shift) & 0xff) as usize + 1] += 1;
+        }
+        for i in 1..counts.len() {
+            counts[i] += counts[i - 1];
+        }
+
+        buffer.clear();
+        buffer.resize(items.len(), items[0]);
+        for item in items.iter() {
+            let digit = ((item.0 >> shift) & 0xff) as usize;
+            buffer[counts[digit]] = *item;
+            counts[digit] += 1;
+        }
+
+        std::mem::swap(items, &mut buffer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const SAMPLE: &str = "32T3K 765
+T55J5 684
+KK677 28
+KTJJT 220
+QQQJA 483";
+
+    fn parse_hands(input: &str, size: usize) -> Vec<Hand> {
+        input.lines().map(|line| {
+            let (cards_str, bid_str) = line.split_once(' ').unwrap();
+            Hand::parse(cards_str, bid_str, size).unwrap()
+        }).collect()
+    }
+
+    #[test]
+    fn report_totals_match_winnings() {
+        let mut hands = parse_hands(SAMPLE, 5);
+        for rules in [RuleSet::standard(), RuleSet::jokers()] {
+            let winnings = total_winnings(&mut hands, &rules);
+            assert_eq!(Report::new(&mut hands, &rules).total(), winnings);
         }
+        assert_eq!(Report::new(&mut [], &RuleSet::standard()).total(), 0);
+    }
+
+    #[test]
+    fn report_rows_explain_every_hand() {
+        let mut hands = parse_hands(SAMPLE, 5);
+        let csv = Report::new(&mut hands, &RuleSet::jokers()).csv("jokers");
+        assert_eq!(csv, "rules,cards,type,substitution,rank,bid,winnings
+jokers,32T3K,one pair,,1,765,765
+jokers,KK677,two pair,,2,28,56
+jokers,T55J5,4 of a kind,5,3,684,2052
+jokers,QQQJA,4 of a kind,Q,4,483,1932
+jokers,KTJJT,4 of a kind,T,5,220,1100
+");
+    }
+
+    #[test]
+    fn hand_parse_errors() {
+        assert_eq!(Hand::parse("32X3K", "765", 5).unwrap_err(), ParseError::UnknownCard { position: 2, found: 'X' });
+        assert_eq!(Hand::parse("32T3", "765", 5).unwrap_err(), ParseError::WrongSize { expected: 5, found: 4 });
+        assert_eq!(Hand::parse("32T3KA", "765", 5).unwrap_err(), ParseError::WrongSize { expected: 5, found: 6 });
+        assert_eq!(Hand::parse("32T3K", "-x", 5).unwrap_err(), ParseError::InvalidBid("-x".to_string()));
+        assert_eq!(Hand::parse("32T3K", "", 5).unwrap_err().to_string(), "invalid bid ''");
+    }
+
+    #[test]
+    fn hands_larger_than_the_key_are_rejected() {
+        let cards = "A".repeat(MAX_HAND_SIZE + 1);
+        assert_eq!(Hand::parse(&cards, "1", MAX_HAND_SIZE + 1).unwrap_err(), ParseError::TooLarge { size: 16, max: 15 });
+        assert_eq!(Hand::parse(&cards, "1", MAX_HAND_SIZE + 1).unwrap_err().to_string(), "hands of 16 cards are larger than the maximum of 15");
+        assert!(Hand::parse(&cards[1..], "1", MAX_HAND_SIZE).is_ok());
+    }
+
+    fn poker_category(cards_str: &str) -> PokerCategory {
+        PokerRules { ace_low: true }.category(&PokerHand::parse(cards_str, "1").unwrap().cards)
+    }
+
+    #[test]
+    fn repeated_suited_cards_are_rejected() {
+        assert_eq!(PokerHand::parse("AsAsAsAsAs", "1").unwrap_err(), ParseError::DuplicateCard { position: 2, found: "As".to_string() });
+        assert_eq!(PokerHand::parse("2h3h4h5h3h", "1").unwrap_err(), ParseError::DuplicateCard { position: 8, found: "3h".to_string() });
+        assert_eq!(PokerHand::parse("AsAsAsAsAs", "1").unwrap_err().to_string(), "duplicate card 'As' at position 2");
     }
 
-    fn cmp(&self, other: &Card) -> Ordering {
-        self.value().cmp(&other.value())
+    #[test]
+    fn poker_parse_errors_are_positioned() {
+        assert_eq!(PokerHand::parse("AsKsQsJsXs", "1").unwrap_err(), ParseError::UnknownCard { position: 8, found: 'X' });
+        assert_eq!(PokerHand::parse("AsKsQxJsTs", "1").unwrap_err(), ParseError::UnknownSuit { position: 5, found: 'x' });
+        assert_eq!(PokerHand::parse("AsKs", "1").unwrap_err(), ParseError::WrongSize { expected: 5, found: 2 });
+        assert_eq!(PokerHand::parse("AsKsQsJsTs", "x").unwrap_err(), ParseError::InvalidBid("x".to_string()));
+    }
+
+    #[test]
+    fn poker_categories() {
+        assert_eq!(poker_category("AsKsQsJsTs"), PokerCategory::StraightFlush);
+        assert_eq!(poker_category("AsAhAdAc2s"), PokerCategory::FourKind);
+        assert_eq!(poker_category("AsAhAdKcKs"), PokerCategory::FullHouse);
+        assert_eq!(poker_category("As9s7s5s3s"), PokerCategory::Flush);
+        assert_eq!(poker_category("As2h3d4c5s"), PokerCategory::Straight);
+        assert_eq!(poker_category("AsAhAd2c5s"), PokerCategory::ThreeKind);
+        assert_eq!(poker_category("AsAh2d2c5s"), PokerCategory::TwoPair);
+        assert_eq!(poker_category("AsAh3d2c5s"), PokerCategory::OnePair);
+        assert_eq!(poker_category("As9h7d5c3s"), PokerCategory::HighCard);
+    }
+
+    #[test]
+    fn radix_sort_of_nothing_is_empty() {
+        let mut items: Vec<(u128, i32)> = vec![];
+        radix_sort(&mut items, 5);
+        assert!(items.is_empty());
+    }
+
+    #[test]
+    fn radix_sort_of_one_item() {
+        let mut items = vec![(0xabcd_u128, 7)];
+        radix_sort(&mut items, 2);
+        assert_eq!(items, vec![(0xabcd, 7)]);
+    }
+
+    proptest! {
+        // few distinct digits, so the stability of equal keys is checked as well
+        #[test]
+        fn radix_sort_matches_stable_sort(bytes in 1..=15usize, digits in prop::collection::vec(0..7u128, 0..300)) {
+            let mask = (1u128 << (8 * bytes)) - 1;
+            let mut items = digits.iter().enumerate().map(|(i, d)| ((d * 0x0101_0101_0101_0101_0101_0101_0101_0101) & mask, i)).collect::<Vec<(u128, usize)>>();
+            let mut expected = items.clone();
+            expected.sort_by_key(|(key, _)| *key);
+
+            radix_sort(&mut items, bytes);
+            prop_assert_eq!(items, expected);
+        }
+    }
+
+    #[test]
+    fn radix_winnings_match_comparison_sort() {
+        let mut hands = parse_hands(SAMPLE, 5);
+        for rules in [RuleSet::standard(), RuleSet::jokers()] {
+            assert_eq!(radix_winnings(&hands, &rules, 5), total_winnings(&mut hands, &rules));
+        }
+        assert_eq!(radix_winnings(&hands, &RuleSet::standard(), 5), 6440);
+        assert_eq!(radix_winnings(&hands, &RuleSet::jokers(), 5), 5905);
+        assert_eq!(radix_winnings(&[], &RuleSet::standard(), 5), 0);
     }
 
-    fn cmp2(&self, other: &Card) -> Ordering {
-        self.value2().cmp(&other.value2())
+    #[test]
+    fn radix_winnings_for_long_hands() {
+        let mut hands = parse_hands("AAAAAAAAAAAAAAA 3\n222222222222222 5\nAAAAAAAAAAAAAAK 7\nKAAAAAAAAAAAAAA 11", 15);
+        let standard = RuleSet::standard();
+        assert_eq!(radix_winnings(&hands, &standard, 15), total_winnings(&mut hands, &standard));
     }
 }
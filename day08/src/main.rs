@@ -1,9 +1,10 @@
+use std::{collections::HashMap, fmt, fs};
+
 fn main() {
     let input = std::fs::read_to_string("day08/data/input.txt").expect("failed to read file");
     let mut lines = input.lines();
 
     let mut directions = vec![];
-    let mut nodes = vec![];
 
     let path_str = lines.next().unwrap();
     for c in path_str.chars() {
@@ -14,128 +15,714 @@ fn main() {
         };
         directions.push(dir);
     }
-    let path_length = directions.len();
-
-    for line in lines.skip(1) {
-        let current_str = &line[0..3];
-        let left_str = &line[7..10];
-        let right_str = &line[12..15];
-        let node = Node::parse(current_str, left_str, right_str);
-        nodes.push(node);
-    }
-    nodes.sort_by(|x, y| x.current.cmp(&y.current));
-
-    let first_node_id = parse_node_id("AAA");
-    let last_node_id = parse_node_id("ZZZ");
-    if let Ok(first_node_index) = nodes.binary_search_by(|n| n.current.cmp(&first_node_id)) {
-        let mut iter_node = &nodes[first_node_index];
-        let mut pos = 0;
-        let mut steps = 0;
-        while iter_node.current != last_node_id {
-            let direction = &directions[pos];
-            let next_id = match direction {
-                Direction::Left => iter_node.left,
-                Direction::Right => iter_node.right
-            };
 
-            iter_node = &nodes[nodes.binary_search_by(|n| n.current.cmp(&next_id)).unwrap()];
-            pos = (pos + 1) % path_length;
-            steps += 1;
-        }
-        println!("steps: {steps}");
-    }
+    // the node lines start after the path, blank lines are skipped while parsing
+    let network = match Network::parse(lines) {
+        Ok(network) => network,
+        Err(err) => panic!("failed to parse network: {err}")
+    };
+
+    // start and end nodes, given as "kind:value" with kind exact, suffix, prefix, regex or list
+    let args = std::env::args().collect::<Vec<String>>();
+    let pattern = |flag: &str, default: NodeMatch| match args.iter().position(|arg| arg == flag).and_then(|i| args.get(i + 1)) {
+        Some(arg) => NodeMatch::parse(arg).unwrap_or_else(|err| panic!("failed to parse {flag}: {err}")),
+        None => default
+    };
+    let start = pattern("--start", NodeMatch::Suffix("A".to_string()));
+    let end = pattern("--end", NodeMatch::Suffix("Z".to_string()));
 
-    let mut iter_nodes = vec![];
-    for node in &nodes {
-        if node.node_type == NodeType::First {
-            iter_nodes.push(node);
+    if let Some(first) = network.index_of("AAA") {
+        match steps_to(&network, &directions, first, &network.mask(&NodeMatch::Exact("ZZZ".to_string()))) {
+            Some(steps) => println!("steps: {steps}"),
+            None => println!("steps: ZZZ is never reached")
         }
     }
-    let mut ghost_steps = vec![];
-    ghost_steps.resize(iter_nodes.len(), 0);
+
+    let iter_nodes = network.matching(&start);
+    let ends = network.mask(&end);
 
     // calculate for each ghost: number of steps until the first finish node
     // then calculate the least common multiple (lcm).
     // This might not work in the general case, but in this graph it works.
-    for i in 0..iter_nodes.len() {
-        let mut pos = 0;
-        let mut iter_node = iter_nodes[i];
-        let ghost_step = &mut ghost_steps[i];
-        while iter_node.node_type != NodeType::Last {
-            let direction = &directions[pos];
-            let next_id = match direction {
-                Direction::Left => iter_node.left,
-                Direction::Right => iter_node.right
+    let ghost_steps = iter_nodes.iter()
+        .map(|&start| steps_to(&network, &directions, start, &ends).map(|steps| steps as u64))
+        .collect::<Option<Vec<u64>>>();
+
+    // lcm algorithm: use num-integer crate
+    match ghost_steps {
+        Some(ghost_steps) if ghost_steps.is_empty() => println!("lcm: no ghosts"),
+        Some(ghost_steps) => {
+            let lcm = ghost_steps.iter().fold(1, |acc, x| num_integer::lcm(acc, *x));
+            println!("lcm: {lcm}");
+        },
+        None => println!("lcm: some ghost never reaches an end node")
+    }
+
+    // the general solution, which does not rely on the structure of the graph
+    let walks = iter_nodes.iter()
+        .map(|&start| Walk::new(&network, &directions, start, &ends))
+        .collect::<Vec<Walk>>();
+    let meetings = Meetings::new(&walks);
+    match meetings.first() {
+        Some(steps) => println!("general ghost steps: {steps}"),
+        None if walks.is_empty() => println!("general ghost steps: no ghosts"),
+        None => println!("general ghost steps: the ghosts are never all on end nodes"),
+    }
+
+    if let Some(limit) = args.iter().position(|arg| arg == "--up-to").and_then(|i| args.get(i + 1)) {
+        let limit = limit.parse().expect("failed to parse --up-to");
+        println!("steps up to {limit} with every ghost on an end node: {:?}", meetings.up_to(limit));
+    }
+
+    // graph export, optionally with the path and cycle of the ghost starting at the given node
+    let ghost = args.iter().position(|arg| arg == "--ghost").and_then(|i| args.get(i + 1)).map(|name| {
+        let index = network.index_of(name).unwrap_or_else(|| panic!("unknown ghost node '{name}'"));
+        Walk::new(&network, &directions, index, &ends)
+    });
+    let export = Export {
+        network: &network,
+        starts: network.mask(&start),
+        ends,
+        roles: ghost.as_ref().map(|walk| walk.edge_roles(&directions)).unwrap_or_default()
+    };
+    if let Some(path) = args.iter().position(|arg| arg == "--dot").and_then(|i| args.get(i + 1)) {
+        fs::write(path, export.dot()).expect("failed to write dot file");
+    }
+    if let Some(path) = args.iter().position(|arg| arg == "--graphml").and_then(|i| args.get(i + 1)) {
+        fs::write(path, export.graphml()).expect("failed to write graphml file");
+    }
+}
+
+// steps from a node until the first node in the target mask, None if the walk loops without reaching one
+fn steps_to(network: &Network, directions: &[Direction], from: usize, targets: &[bool]) -> Option<usize> {
+    let mut visited = vec![false; network.len() * directions.len()];
+    let mut index = from;
+    let mut step = 0;
+
+    while !targets[index] {
+        let pos = step % directions.len();
+        let state = index * directions.len() + pos;
+        if visited[state] {
+            return None;
+        }
+        visited[state] = true;
+
+        index = network.next(index, &directions[pos]);
+        step += 1;
+    }
+
+    Some(step)
+}
+
+// walk of a single ghost through the states (node, position in the directions),
+// which has to run into a cycle after at most nodes * directions steps
+#[derive(Debug)]
+struct Walk {
+    prefix: usize,          // steps before the cycle starts
+    cycle: usize,           // length of the cycle
+    prefix_hits: Vec<usize>, // steps before the cycle at which the ghost is on an end node
+    cycle_hits: Vec<usize>,  // steps within the first round of the cycle at which the ghost is on an end node
+    states: Vec<(usize, usize)> // node and position in the directions for every step up to the end of the first round
+}
+
+impl Walk {
+    fn new(network: &Network, directions: &[Direction], start: usize, ends: &[bool]) -> Walk {
+        let mut first_visit = vec![usize::MAX; network.len() * directions.len()];
+        let mut hits = vec![];
+        let mut states = vec![];
+        let mut index = start;
+        let mut step = 0;
+
+        loop {
+            let pos = step % directions.len();
+            let state = index * directions.len() + pos;
+            if first_visit[state] != usize::MAX {
+                let prefix = first_visit[state];
+                let (prefix_hits, cycle_hits) = hits.iter().partition(|&&hit| hit < prefix);
+                return Walk { prefix, cycle: step - prefix, prefix_hits, cycle_hits, states };
+            }
+            first_visit[state] = step;
+            states.push((index, pos));
+
+            if ends[index] {
+                hits.push(step);
+            }
+
+            index = network.next(index, &directions[pos]);
+            step += 1;
+        }
+    }
+
+    fn is_hit(&self, step: usize) -> bool {
+        if step < self.prefix {
+            return self.prefix_hits.contains(&step);
+        }
+        let in_cycle = self.prefix + (step - self.prefix) % self.cycle;
+        self.cycle_hits.contains(&in_cycle)
+    }
+
+    // the edges the ghost takes, an edge taken both before and within the cycle counts as cycle edge
+    fn edge_roles(&self, directions: &[Direction]) -> HashMap<(usize, Direction), EdgeRole> {
+        let mut roles = HashMap::new();
+        for (step, &(index, pos)) in self.states.iter().enumerate() {
+            let role = if step < self.prefix { EdgeRole::Path } else { EdgeRole::Cycle };
+            let entry = roles.entry((index, directions[pos])).or_insert(role);
+            if role == EdgeRole::Cycle {
+                *entry = role;
+            }
+        }
+        roles
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+enum EdgeRole {
+    Path, // taken by a ghost before its cycle
+    Cycle // taken by a ghost within its cycle
+}
+
+impl EdgeRole {
+    fn name(&self) -> &'static str {
+        match self {
+            EdgeRole::Path => "path",
+            EdgeRole::Cycle => "cycle"
+        }
+    }
+
+    fn color(&self) -> &'static str {
+        match self {
+            EdgeRole::Path => "blue",
+            EdgeRole::Cycle => "red"
+        }
+    }
+}
+
+// the network as graph file, with start and end nodes marked and the edges of a ghost's walk highlighted
+struct Export<'a> {
+    network: &'a Network,
+    starts: Vec<bool>,
+    ends: Vec<bool>,
+    roles: HashMap<(usize, Direction), EdgeRole>
+}
+
+impl Export<'_> {
+    fn node_role(&self, index: usize) -> &'static str {
+        match (self.starts[index], self.ends[index]) {
+            (true, true) => "start end",
+            (true, false) => "start",
+            (false, true) => "end",
+            (false, false) => "normal"
+        }
+    }
+
+    fn edges(&self) -> Vec<(usize, usize, Direction, Option<EdgeRole>)> {
+        let mut edges = vec![];
+        for index in 0..self.network.len() {
+            for direction in [Direction::Left, Direction::Right] {
+                let target = self.network.next(index, &direction);
+                edges.push((index, target, direction, self.roles.get(&(index, direction)).copied()));
+            }
+        }
+        edges
+    }
+
+    fn dot(&self) -> String {
+        let quote = |name: &str| format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut out = String::from("digraph network {\n    node [shape=circle];\n");
+
+        for (index, name) in self.network.names.iter().enumerate() {
+            let style = match (self.starts[index], self.ends[index]) {
+                (true, true) => " [style=filled, fillcolor=gold]",
+                (true, false) => " [style=filled, fillcolor=palegreen]",
+                (false, true) => " [style=filled, fillcolor=lightcoral]",
+                (false, false) => ""
             };
+            out.push_str(&format!("    {}{style};\n", quote(name)));
+        }
 
-            iter_node = &nodes[nodes.binary_search_by(|n| n.current.cmp(&next_id)).unwrap()];
-            pos = (pos + 1) % path_length;
-            *ghost_step += 1;
+        for (from, to, direction, role) in self.edges() {
+            let style = role.map_or(String::new(), |role| format!(", color={}, penwidth=2", role.color()));
+            let (from, to) = (&self.network.names[from], &self.network.names[to]);
+            out.push_str(&format!("    {} -> {} [label=\"{}\"{style}];\n", quote(from), quote(to), direction.char()));
         }
+
+        out.push_str("}\n");
+        out
     }
 
-    // lcm algorithm: use num-integer crate
-    let lcm = ghost_steps.iter().fold(1 as u64, |acc, x| num_integer::lcm(acc, *x));
-    println!("lcm: {lcm}");
+    fn graphml(&self) -> String {
+        let escape = |name: &str| name.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;");
+        let mut out = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"role\" for=\"node\" attr.name=\"role\" attr.type=\"string\"/>\n",
+            "  <key id=\"direction\" for=\"edge\" attr.name=\"direction\" attr.type=\"string\"/>\n",
+            "  <key id=\"walk\" for=\"edge\" attr.name=\"walk\" attr.type=\"string\"/>\n",
+            "  <graph id=\"network\" edgedefault=\"directed\">\n"
+        ));
+
+        for (index, name) in self.network.names.iter().enumerate() {
+            out.push_str(&format!("    <node id=\"{}\"><data key=\"role\">{}</data></node>\n", escape(name), self.node_role(index)));
+        }
+
+        for (from, to, direction, role) in self.edges() {
+            let walk = role.map_or(String::new(), |role| format!("<data key=\"walk\">{}</data>", role.name()));
+            let (from, to) = (&self.network.names[from], &self.network.names[to]);
+            out.push_str(&format!("    <edge source=\"{}\" target=\"{}\"><data key=\"direction\">{}</data>{walk}</edge>\n",
+                escape(from), escape(to), direction.char()));
+        }
+
+        out.push_str("  </graph>\n</graphml>\n");
+        out
+    }
 }
 
+// the steps at which every ghost is on an end node: the early ones before every ghost is in its cycle,
+// and afterwards the steps from start on that are congruent to one of the residues
 #[derive(Debug)]
+struct Meetings {
+    early: Vec<i128>,
+    residues: Vec<i128>,
+    modulus: i128,
+    start: i128
+}
+
+impl Meetings {
+    fn new(walks: &[Walk]) -> Meetings {
+        // before every ghost is in its cycle, only the finitely many prefix hits are candidates
+        let mut early = walks.iter().flat_map(|walk| walk.prefix_hits.iter().copied())
+            .filter(|&step| walks.iter().all(|walk| walk.is_hit(step)))
+            .map(|step| step as i128)
+            .collect::<Vec<i128>>();
+        early.sort();
+        early.dedup();
+
+        // afterwards every ghost needs step = hit (mod cycle) for one of its cycle hits,
+        // the congruences are merged ghost by ghost, keeping every residue which is still possible;
+        // without ghosts there is nobody to meet, so there are no steps at all
+        let mut residues = if walks.is_empty() { vec![] } else { vec![0i128] };
+        let mut modulus = 1i128;
+        for walk in walks {
+            let mut merged = vec![];
+            for &residue in &residues {
+                for &hit in &walk.cycle_hits {
+                    if let Some(solution) = crt(residue, modulus, hit as i128, walk.cycle as i128) {
+                        merged.push(solution);
+                    }
+                }
+            }
+            modulus = num_integer::lcm(modulus, walk.cycle as i128);
+            merged.sort();
+            merged.dedup();
+            residues = merged;
+        }
+
+        let start = walks.iter().map(|walk| walk.prefix as i128).max().unwrap_or(0);
+        Meetings { early, residues, modulus, start }
+    }
+
+    // smallest step at least start which is congruent to the residue
+    fn lift(&self, residue: i128) -> i128 {
+        if residue >= self.start {
+            residue
+        } else {
+            residue + (self.start - residue + self.modulus - 1) / self.modulus * self.modulus
+        }
+    }
+
+    fn first(&self) -> Option<i128> {
+        self.early.first().copied().or_else(|| self.residues.iter().map(|&residue| self.lift(residue)).min())
+    }
+
+    fn up_to(&self, limit: i128) -> Vec<i128> {
+        let mut steps = self.early.iter().copied().filter(|&step| step <= limit).collect::<Vec<i128>>();
+        let mut late = vec![];
+        for &residue in &self.residues {
+            late.extend((self.lift(residue)..=limit).step_by(self.modulus as usize));
+        }
+        late.sort();
+        steps.extend(late);
+        steps
+    }
+}
+
+// x with x = a (mod n) and x = b (mod m), in [0, lcm(n, m)), for moduli which need not be coprime
+fn crt(a: i128, n: i128, b: i128, m: i128) -> Option<i128> {
+    let gcd = num_integer::Integer::extended_gcd(&n, &m);
+    if (b - a) % gcd.gcd != 0 {
+        return None;
+    }
+
+    let lcm = n / gcd.gcd * m;
+    let k = ((b - a) / gcd.gcd % (m / gcd.gcd)) * gcd.x % (m / gcd.gcd);
+    Some((a + n * k).rem_euclid(lcm))
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
 enum Direction {
     Left,
     Right
 }
 
-#[derive(PartialEq,Debug,Clone,Copy)]
-enum NodeType {
-    First,
-    Last,
-    Normal
+impl Direction {
+    fn char(&self) -> char {
+        match self {
+            Direction::Left => 'L',
+            Direction::Right => 'R'
+        }
+    }
 }
 
-#[derive(Debug,Clone,Copy)]
-struct Node {
-    current: u64,
-    left: u64,
-    right: u64,
-    node_type: NodeType
+// set of node names
+#[derive(Debug)]
+enum NodeMatch {
+    Exact(String),
+    Suffix(String),
+    Prefix(String),
+    Regex(regex::Regex),
+    List(Vec<String>)
 }
 
-impl Node {
-    fn parse(current_str: &str, left_str: &str, right_str: &str) -> Node {
-        let mut nums = [0;3];
+#[derive(Debug)]
+enum MatchError {
+    UnknownKind(String),
+    InvalidRegex(regex::Error)
+}
 
-        for (i,  s) in [current_str, left_str, right_str].iter().enumerate() {
-            nums[i] = parse_node_id(s);
+impl fmt::Display for MatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MatchError::UnknownKind(kind) => write!(f, "unknown kind '{kind}', expected exact, suffix, prefix, regex or list"),
+            MatchError::InvalidRegex(err) => write!(f, "invalid regex: {err}"),
         }
+    }
+}
 
-        let node_type = match current_str.chars().skip(2).next().unwrap() {
-            'A' => NodeType::First,
-            'Z' => NodeType::Last,
-            _ => NodeType::Normal
-        };
+impl NodeMatch {
+    // "kind:value", a list is separated by commas
+    fn parse(s: &str) -> Result<NodeMatch, MatchError> {
+        let (kind, value) = s.split_once(':').ok_or_else(|| MatchError::UnknownKind(s.to_string()))?;
+        match kind {
+            "exact" => Ok(NodeMatch::Exact(value.to_string())),
+            "suffix" => Ok(NodeMatch::Suffix(value.to_string())),
+            "prefix" => Ok(NodeMatch::Prefix(value.to_string())),
+            "regex" => regex::Regex::new(value).map(NodeMatch::Regex).map_err(MatchError::InvalidRegex),
+            "list" => Ok(NodeMatch::List(value.split(',').map(|name| name.trim().to_string()).collect())),
+            _ => Err(MatchError::UnknownKind(kind.to_string()))
+        }
+    }
 
-        Node {
-            current: nums[0],
-            left: nums[1],
-            right: nums[2],
-            node_type
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            NodeMatch::Exact(exact) => name == exact,
+            NodeMatch::Suffix(suffix) => name.ends_with(suffix.as_str()),
+            NodeMatch::Prefix(prefix) => name.starts_with(prefix.as_str()),
+            NodeMatch::Regex(regex) => regex.is_match(name),
+            NodeMatch::List(names) => names.iter().any(|n| n == name)
         }
     }
 }
 
-fn parse_node_id(s: &str) -> u64 {
-    let l = s.len();
-    if l != 3 {
-        panic!("failed to parse node id");
+// errors while parsing the node lines, with 1-based line numbers within the node section
+#[derive(Debug)]
+enum NetworkError {
+    Malformed { line: usize, text: String },
+    Duplicate { line: usize, name: String },
+    Dangling { line: usize, name: String }
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::Malformed { line, text } => write!(f, "line {line}: expected 'NAME = (LEFT, RIGHT)', found '{text}'"),
+            NetworkError::Duplicate { line, name } => write!(f, "line {line}: node '{name}' is defined twice"),
+            NetworkError::Dangling { line, name } => write!(f, "line {line}: node '{name}' is never defined"),
+        }
     }
+}
+
+// nodes interned to dense indices, the successors are stored as index arrays
+#[derive(Debug)]
+struct Network {
+    names: Vec<String>,
+    left: Vec<usize>,
+    right: Vec<usize>,
+    indices: HashMap<String, usize>
+}
+
+impl Network {
+    fn parse<'a>(lines: impl Iterator<Item = &'a str>) -> Result<Network, NetworkError> {
+        let mut definitions = vec![];
+        for (i, line) in lines.enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let malformed = || NetworkError::Malformed { line: i + 1, text: line.to_string() };
+
+            let (name, targets) = line.split_once('=').ok_or_else(malformed)?;
+            let targets = targets.trim().strip_prefix('(').and_then(|t| t.strip_suffix(')')).ok_or_else(malformed)?;
+            let (left, right) = targets.split_once(',').ok_or_else(malformed)?;
+            let (name, left, right) = (name.trim(), left.trim(), right.trim());
+            if [name, left, right].iter().any(|s| s.is_empty() || s.contains(char::is_whitespace)) {
+                return Err(malformed());
+            }
+            definitions.push((i + 1, name, left, right));
+        }
+
+        // first pass: every defined name gets the next index
+        let mut indices = HashMap::new();
+        let mut names = vec![];
+        for &(line, name, _, _) in &definitions {
+            if indices.insert(name.to_string(), names.len()).is_some() {
+                return Err(NetworkError::Duplicate { line, name: name.to_string() });
+            }
+            names.push(name.to_string());
+        }
+
+        // second pass: resolve the successors
+        let mut left = vec![];
+        let mut right = vec![];
+        for &(line, _, l, r) in &definitions {
+            let resolve = |name: &str| indices.get(name).copied()
+                .ok_or_else(|| NetworkError::Dangling { line, name: name.to_string() });
+            left.push(resolve(l)?);
+            right.push(resolve(r)?);
+        }
+
+        Ok(Network { names, left, right, indices })
+    }
+
+    fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    fn index_of(&self, name: &str) -> Option<usize> {
+        self.indices.get(name).copied()
+    }
+
+    fn next(&self, index: usize, direction: &Direction) -> usize {
+        match direction {
+            Direction::Left => self.left[index],
+            Direction::Right => self.right[index]
+        }
+    }
+
+    // whether each node matches the pattern
+    fn mask(&self, pattern: &NodeMatch) -> Vec<bool> {
+        self.names.iter().map(|name| pattern.matches(name)).collect()
+    }
+
+    fn matching(&self, pattern: &NodeMatch) -> Vec<usize> {
+        (0..self.len()).filter(|&index| pattern.matches(&self.names[index])).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GHOSTS: &str = "11A = (11B, XXX)
+11B = (XXX, 11Z)
+11Z = (11B, XXX)
+22A = (22B, XXX)
+22B = (22C, 22C)
+22C = (22Z, 22Z)
+22Z = (22B, 22B)
+XXX = (XXX, XXX)";
 
-    let base = 10 + 26 + 26; // 0-9, a-z, A-Z
-    let mut id = 0;
-    let mut factor = 1;
-    for c in s.chars().rev() {
-        id += (c as u64 - '0' as u64) * factor;
-        factor *= base;
+    fn walks(input: &str, start: &NodeMatch) -> Vec<Walk> {
+        let network = Network::parse(input.lines()).unwrap();
+        let directions = [Direction::Left, Direction::Right];
+        let ends = network.mask(&NodeMatch::Suffix("Z".to_string()));
+        network.matching(start).iter().map(|&index| Walk::new(&network, &directions, index, &ends)).collect()
     }
 
-    id
+    fn parse_error(input: &str) -> String {
+        Network::parse(input.lines()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn network_with_long_names() {
+        let network = Network::parse("START = (MIDDLE, END)\nMIDDLE = (END, START)\n\nEND = (END, END)".lines()).unwrap();
+        assert_eq!(network.len(), 3);
+        let start = network.index_of("START").unwrap();
+        assert_eq!(network.next(start, &Direction::Left), network.index_of("MIDDLE").unwrap());
+        assert_eq!(network.next(start, &Direction::Right), network.index_of("END").unwrap());
+        assert_eq!(network.index_of("STA"), None);
+    }
+
+    #[test]
+    fn network_without_blank_separator_keeps_its_first_node() {
+        let network = Network::parse("AAA = (BBB, BBB)\nBBB = (AAA, AAA)".lines()).unwrap();
+        assert_eq!(network.names, vec!["AAA", "BBB"]);
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected() {
+        assert_eq!(parse_error("AAA = (BBB, BBB)\nBBB (AAA, AAA)"), "line 2: expected 'NAME = (LEFT, RIGHT)', found 'BBB (AAA, AAA)'");
+        assert_eq!(parse_error("AAA = BBB, BBB"), "line 1: expected 'NAME = (LEFT, RIGHT)', found 'AAA = BBB, BBB'");
+        assert_eq!(parse_error("AAA = (BBB)"), "line 1: expected 'NAME = (LEFT, RIGHT)', found 'AAA = (BBB)'");
+        assert_eq!(parse_error("A A = (BBB, BBB)"), "line 1: expected 'NAME = (LEFT, RIGHT)', found 'A A = (BBB, BBB)'");
+        assert_eq!(parse_error(" = (BBB, BBB)"), "line 1: expected 'NAME = (LEFT, RIGHT)', found ' = (BBB, BBB)'");
+    }
+
+    #[test]
+    fn duplicate_nodes_are_rejected() {
+        assert_eq!(parse_error("AAA = (AAA, AAA)\n\nAAA = (AAA, AAA)"), "line 3: node 'AAA' is defined twice");
+    }
+
+    #[test]
+    fn dangling_nodes_are_rejected() {
+        assert_eq!(parse_error("AAA = (AAA, BBB)"), "line 1: node 'BBB' is never defined");
+        assert_eq!(parse_error("AAA = (AAA, AAA)\nBBB = (CCC, AAA)"), "line 2: node 'CCC' is never defined");
+    }
+
+    #[test]
+    fn node_matches_of_every_kind() {
+        let names = ["11A", "11B", "11Z", "22A", "XXX"];
+        let matching = |pattern: &str| {
+            let pattern = NodeMatch::parse(pattern).unwrap();
+            names.iter().filter(|name| pattern.matches(name)).copied().collect::<Vec<&str>>()
+        };
+
+        assert_eq!(matching("exact:11B"), vec!["11B"]);
+        assert_eq!(matching("suffix:A"), vec!["11A", "22A"]);
+        assert_eq!(matching("prefix:11"), vec!["11A", "11B", "11Z"]);
+        assert_eq!(matching("regex:^[0-9]+[AZ]$"), vec!["11A", "11Z", "22A"]);
+        assert_eq!(matching("list:XXX, 11Z,nope"), vec!["11Z", "XXX"]);
+        assert_eq!(matching("exact:"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn invalid_node_matches_are_rejected() {
+        assert!(matches!(NodeMatch::parse("glob:*A"), Err(MatchError::UnknownKind(kind)) if kind == "glob"));
+        assert!(matches!(NodeMatch::parse("11A"), Err(MatchError::UnknownKind(kind)) if kind == "11A"));
+        assert!(matches!(NodeMatch::parse("regex:(A"), Err(MatchError::InvalidRegex(_))));
+        assert!(NodeMatch::parse("glob:*A").unwrap_err().to_string().starts_with("unknown kind 'glob'"));
+    }
+
+    #[test]
+    fn steps_to_the_first_matching_node() {
+        let network = Network::parse(GHOSTS.lines()).unwrap();
+        let directions = [Direction::Left, Direction::Right];
+        let steps = |from: &str, pattern: &str| {
+            let targets = network.mask(&NodeMatch::parse(pattern).unwrap());
+            steps_to(&network, &directions, network.index_of(from).unwrap(), &targets)
+        };
+
+        assert_eq!(steps("11A", "exact:11Z"), Some(2));
+        assert_eq!(steps("22A", "suffix:Z"), Some(3));
+        assert_eq!(steps("11A", "prefix:11"), Some(0));
+        assert_eq!(steps("11A", "list:XXX"), None);
+        assert_eq!(steps("XXX", "exact:11A"), None);
+    }
+
+    // names which need quoting in dot and escaping in graphml, a start, an end and a node which is both
+    const ESCAPED: &str = "S\"A = (M&, E<Z)
+M& = (E<Z, S\"A)
+E<Z = (E<Z, M&)
+SZ = (SZ, M&)";
+
+    // the ghost from S"A only goes left: two path edges, then it loops on E<Z
+    fn export_escaped(test: impl Fn(&Export)) {
+        let network = Network::parse(ESCAPED.lines()).unwrap();
+        let directions = [Direction::Left];
+        let ends = network.mask(&NodeMatch::Suffix("Z".to_string()));
+        let walk = Walk::new(&network, &directions, network.index_of("S\"A").unwrap(), &ends);
+        let export = Export {
+            network: &network,
+            starts: network.mask(&NodeMatch::Prefix("S".to_string())),
+            ends,
+            roles: walk.edge_roles(&directions)
+        };
+        test(&export);
+    }
+
+    #[test]
+    fn edge_roles_of_a_walk() {
+        export_escaped(|export| {
+            let edge = |name: &str| (export.network.index_of(name).unwrap(), Direction::Left);
+            assert_eq!(export.roles.len(), 3);
+            assert_eq!(export.roles[&edge("S\"A")], EdgeRole::Path);
+            assert_eq!(export.roles[&edge("M&")], EdgeRole::Path);
+            assert_eq!(export.roles[&edge("E<Z")], EdgeRole::Cycle);
+        });
+    }
+
+    #[test]
+    fn dot_export() {
+        export_escaped(|export| assert_eq!(export.dot(), r#"digraph network {
+    node [shape=circle];
+    "S\"A" [style=filled, fillcolor=palegreen];
+    "M&";
+    "E<Z" [style=filled, fillcolor=lightcoral];
+    "SZ" [style=filled, fillcolor=gold];
+    "S\"A" -> "M&" [label="L", color=blue, penwidth=2];
+    "S\"A" -> "E<Z" [label="R"];
+    "M&" -> "E<Z" [label="L", color=blue, penwidth=2];
+    "M&" -> "S\"A" [label="R"];
+    "E<Z" -> "E<Z" [label="L", color=red, penwidth=2];
+    "E<Z" -> "M&" [label="R"];
+    "SZ" -> "SZ" [label="L"];
+    "SZ" -> "M&" [label="R"];
+}
+"#));
+    }
+
+    #[test]
+    fn graphml_export() {
+        export_escaped(|export| {
+            let graphml = export.graphml();
+            assert!(graphml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<graphml"));
+            assert!(graphml.ends_with("  </graph>\n</graphml>\n"));
+
+            let body = graphml.lines().filter(|line| line.starts_with("    <")).collect::<Vec<&str>>();
+            assert_eq!(body, vec![
+                r#"    <node id="S&quot;A"><data key="role">start</data></node>"#,
+                r#"    <node id="M&amp;"><data key="role">normal</data></node>"#,
+                r#"    <node id="E&lt;Z"><data key="role">end</data></node>"#,
+                r#"    <node id="SZ"><data key="role">start end</data></node>"#,
+                r#"    <edge source="S&quot;A" target="M&amp;"><data key="direction">L</data><data key="walk">path</data></edge>"#,
+                r#"    <edge source="S&quot;A" target="E&lt;Z"><data key="direction">R</data></edge>"#,
+                r#"    <edge source="M&amp;" target="E&lt;Z"><data key="direction">L</data><data key="walk">path</data></edge>"#,
+                r#"    <edge source="M&amp;" target="S&quot;A"><data key="direction">R</data></edge>"#,
+                r#"    <edge source="E&lt;Z" target="E&lt;Z"><data key="direction">L</data><data key="walk">cycle</data></edge>"#,
+                r#"    <edge source="E&lt;Z" target="M&amp;"><data key="direction">R</data></edge>"#,
+                r#"    <edge source="SZ" target="SZ"><data key="direction">L</data></edge>"#,
+                r#"    <edge source="SZ" target="M&amp;"><data key="direction">R</data></edge>"#
+            ]);
+        });
+    }
+
+    #[test]
+    fn export_without_a_walk_has_no_roles() {
+        let network = Network::parse("A\\B = (A\\B, A\\B)".lines()).unwrap();
+        let export = Export { network: &network, starts: vec![false], ends: vec![false], roles: HashMap::new() };
+        assert!(export.dot().contains("    \"A\\\\B\" -> \"A\\\\B\" [label=\"L\"];\n"));
+        assert!(!export.graphml().contains("walk\">"));
+    }
+
+    #[test]
+    fn ghosts_meet_on_end_nodes() {
+        let meetings = Meetings::new(&walks(GHOSTS, &NodeMatch::Suffix("A".to_string())));
+        assert_eq!(meetings.first(), Some(6));
+        assert_eq!(meetings.up_to(20), vec![6, 12, 18]);
+    }
+
+    #[test]
+    fn no_ghosts_never_meet() {
+        let walks = walks(GHOSTS, &NodeMatch::Exact("none".to_string()));
+        assert!(walks.is_empty());
+
+        let meetings = Meetings::new(&walks);
+        assert_eq!(meetings.first(), None);
+        assert_eq!(meetings.up_to(20), vec![]);
+    }
+
+    #[test]
+    fn single_ghost_meets_itself() {
+        let meetings = Meetings::new(&walks(GHOSTS, &NodeMatch::Exact("11A".to_string())));
+        assert_eq!(meetings.first(), Some(2));
+        assert_eq!(meetings.up_to(7), vec![2, 4, 6]);
+    }
 }
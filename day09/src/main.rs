@@ -1,48 +1,304 @@
-use std::iter::zip;
+use std::{fmt, iter::zip};
+use num_bigint::BigInt;
 
 fn main() {
     let input = std::fs::read_to_string("day09/data/input.txt").expect("failed to read file");
-    let (sum_prev, sum_next): (i32, i32) = input.lines().map(|line| History::parse(line).predict()).fold((0,0), |(acc_x, acc_y), (x, y)| (acc_x + x, acc_y + y));
+    // values which do not fit into i64 fall back to big integers
+    let (sum_prev, sum_next) = match predict_all::<i64>(&input) {
+        Some((prev, next)) => (prev.to_string(), next.to_string()),
+        None => {
+            let (prev, next) = predict_all::<BigInt>(&input).expect("failed to parse values");
+            (prev.to_string(), next.to_string())
+        }
+    };
     println!("sum_prev: {sum_prev}");
     println!("sum_next: {sum_next}");
+
+    // far extrapolation, big integers never overflow
+    let args = std::env::args().collect::<Vec<String>>();
+    let steps = args.iter().position(|arg| arg == "--steps").and_then(|i| args.get(i + 1))
+        .map_or(1_000_000_000_000, |steps| steps.parse().expect("failed to parse --steps"));
+    match extrapolate::<i128>(&input, steps) {
+        Some((prev, next)) => println!("sums {steps} steps back and ahead with i128: {prev} {next}"),
+        None => println!("sums {steps} steps back and ahead with i128: overflow")
+    }
+    let (prev, next) = extrapolate::<BigInt>(&input, steps).expect("big integers overflowed");
+    println!("sums {steps} steps back and ahead with big integers: {prev} {next}");
+}
+
+// sums over all histories of the previous and next value, None if a value does not fit
+fn predict_all<T: Number>(input: &str) -> Option<(T, T)> {
+    let mut sum_prev = T::zero();
+    let mut sum_next = T::zero();
+
+    for line in input.lines() {
+        let (prev, next) = History::<T>::parse(line)?.predict()?;
+        sum_prev = sum_prev.try_add(&prev)?;
+        sum_next = sum_next.try_add(&next)?;
+    }
+
+    Some((sum_prev, sum_next))
+}
+
+// sums over all histories of the values the given number of steps before the first and after the last value
+fn extrapolate<T: Number>(input: &str, steps: i64) -> Option<(T, T)> {
+    let mut sum_prev = T::zero();
+    let mut sum_next = T::zero();
+
+    for line in input.lines() {
+        let newton = Newton::new(&History::<T>::parse(line)?)?;
+        sum_prev = sum_prev.try_add(&newton.prev(steps)?)?;
+        sum_next = sum_next.try_add(&newton.next(steps)?)?;
+    }
+
+    Some((sum_prev, sum_next))
+}
+
+// number type for the values, None signals an overflow
+trait Number: Clone + PartialEq + fmt::Display {
+    fn zero() -> Self;
+    fn from_i64(n: i64) -> Self;
+    fn parse(s: &str) -> Option<Self>;
+    fn try_add(&self, other: &Self) -> Option<Self>;
+    fn try_sub(&self, other: &Self) -> Option<Self>;
+    fn try_mul(&self, other: &Self) -> Option<Self>;
+    fn try_div(&self, other: &Self) -> Option<Self>;
+}
+
+macro_rules! impl_number {
+    ($t:ty) => {
+        impl Number for $t {
+            fn zero() -> Self {
+                0
+            }
+
+            fn from_i64(n: i64) -> Self {
+                n as $t
+            }
+
+            fn parse(s: &str) -> Option<Self> {
+                s.parse().ok()
+            }
+
+            fn try_add(&self, other: &Self) -> Option<Self> {
+                self.checked_add(*other)
+            }
+
+            fn try_sub(&self, other: &Self) -> Option<Self> {
+                self.checked_sub(*other)
+            }
+
+            fn try_mul(&self, other: &Self) -> Option<Self> {
+                self.checked_mul(*other)
+            }
+
+            fn try_div(&self, other: &Self) -> Option<Self> {
+                self.checked_div(*other)
+            }
+        }
+    };
+}
+
+impl_number!(i64);
+impl_number!(i128);
+
+impl Number for BigInt {
+    fn zero() -> Self {
+        BigInt::from(0)
+    }
+
+    fn from_i64(n: i64) -> Self {
+        BigInt::from(n)
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        s.parse().ok()
+    }
+
+    fn try_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn try_sub(&self, other: &Self) -> Option<Self> {
+        Some(self - other)
+    }
+
+    fn try_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn try_div(&self, other: &Self) -> Option<Self> {
+        if *other == BigInt::from(0) { None } else { Some(self / other) }
+    }
 }
 
 #[derive(Debug, Clone)]
-struct History {
-    values: Vec<i32>
+struct History<T> {
+    values: Vec<T>
 }
 
-impl History {
-    fn parse(s: &str) -> History {
-        History {
-            values: s.split_whitespace().map(|x| x.parse().unwrap()).collect()
+impl<T: Number> History<T> {
+    // None if a value is no number or does not fit
+    fn parse(s: &str) -> Option<History<T>> {
+        Some(History {
+            values: s.split_whitespace().map(T::parse).collect::<Option<Vec<T>>>()?
+        })
+    }
+
+    fn differences(&self) -> Option<History<T>> {
+        Some(History {
+            values: zip(self.values.iter(), self.values.iter().skip(1)).map(|(x, y)| y.try_sub(x)).collect::<Option<Vec<T>>>()?
+        })
+    }
+
+    fn is_zero(&self) -> bool {
+        self.values.iter().all(|x| *x == T::zero())
+    }
+
+    // values one step before the first and after the last one, None also if an intermediate product overflows
+    fn predict(&self) -> Option<(T, T)> {
+        let newton = Newton::new(self)?;
+        Some((newton.prev(1)?, newton.next(1)?))
+    }
+}
+
+// the polynomial through the values in Newton's forward difference form,
+// value(x) = sum over k of binomial(x, k) * (k-th difference at index 0),
+// so every index is reached without iterating the difference triangle
+#[derive(Debug)]
+struct Newton<T> {
+    leading: Vec<T>, // first value of every row of the difference triangle, up to the last non-zero row
+    len: usize
+}
+
+impl<T: Number> Newton<T> {
+    fn new(history: &History<T>) -> Option<Newton<T>> {
+        let mut leading = vec![];
+        let mut row = history.clone();
+
+        while !row.is_zero() {
+            leading.push(row.values[0].clone());
+            row = row.differences()?;
         }
+
+        Some(Newton { leading, len: history.values.len() })
     }
 
-    fn differences(&self) -> History {
-        History {
-            values: zip(self.values.iter(), self.values.iter().skip(1)).map(|(x, y)| y - x).collect()
+    // value at the index, negative indices lie before the first value
+    fn at(&self, x: i64) -> Option<T> {
+        let mut value = T::zero();
+        let mut binomial = T::from_i64(1);
+
+        for (k, difference) in self.leading.iter().enumerate() {
+            if k > 0 {
+                // binomial(x, k) = binomial(x, k - 1) * (x - k + 1) / k, the division is exact
+                let factor = T::from_i64(x.checked_sub(k as i64 - 1)?);
+                binomial = binomial.try_mul(&factor)?.try_div(&T::from_i64(k as i64))?;
+            }
+            value = value.try_add(&binomial.try_mul(difference)?)?;
         }
+
+        Some(value)
     }
 
-    fn is_zero(&self) -> bool {
-        self.values.iter().all(|x| *x == 0)
+    // value k steps after the last one
+    fn next(&self, k: i64) -> Option<T> {
+        self.at((self.len as i64 - 1).checked_add(k)?)
+    }
+
+    // value k steps before the first one
+    fn prev(&self, k: i64) -> Option<T> {
+        self.at(k.checked_neg()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const SAMPLE: &str = "0 3 6 9 12 15
+1 3 6 10 15 21
+10 13 16 21 30 45";
+
+    // the value after the last one by extending the difference triangle
+    fn triangle_next(values: &[i128]) -> i128 {
+        if values.iter().all(|x| *x == 0) {
+            return 0;
+        }
+        let differences = zip(values.iter(), values.iter().skip(1)).map(|(x, y)| y - x).collect::<Vec<i128>>();
+        values.last().unwrap() + triangle_next(&differences)
+    }
+
+    // the value k steps after the last one by extending the triangle k times
+    fn triangle_steps(values: &[i128], k: i64) -> i128 {
+        let mut values = values.to_vec();
+        for _ in 0..k {
+            values.push(triangle_next(&values));
+        }
+        *values.last().unwrap()
+    }
+
+    #[test]
+    fn sample() {
+        assert_eq!(predict_all::<i64>(SAMPLE), Some((2, 114)));
+        assert_eq!(predict_all::<BigInt>(SAMPLE), Some((BigInt::from(2), BigInt::from(114))));
     }
 
-    fn predict(&self) -> (i32, i32) {
-        let mut histories = vec![self.clone()];
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(2000))]
 
-        while !histories.last().unwrap().is_zero() {
-            histories.push(histories.last().unwrap().differences());
+        #[test]
+        fn closed_form_matches_triangle(values in prop::collection::vec(-1000..=1000i128, 1..9)) {
+            let newton = Newton::new(&History { values: values.clone() }).unwrap();
+            let reversed = values.iter().rev().copied().collect::<Vec<i128>>();
+
+            for k in 0..6 {
+                prop_assert_eq!(newton.next(k), Some(triangle_steps(&values, k)), "next {}", k);
+                prop_assert_eq!(newton.prev(k), Some(triangle_steps(&reversed, k)), "prev {}", k);
+            }
         }
 
-        let mut prev = 0;
-        let mut next = 0;
-        for history in histories.iter().rev() {
-            prev = history.values.first().unwrap() - prev;
-            next = history.values.last().unwrap() + next;
+        #[test]
+        fn closed_form_matches_triangle_for_polynomials(coefficients in prop::collection::vec(-10..=10i128, 1..6), extra in 0..4usize) {
+            let polynomial = |x: i128| coefficients.iter().rev().fold(0, |acc, c| acc * x + c);
+            let values = (0..(coefficients.len() + extra) as i128).map(polynomial).collect::<Vec<i128>>();
+            let newton = Newton::new(&History { values: values.clone() }).unwrap();
+
+            for k in [1, 2, 10, 1000] {
+                prop_assert_eq!(newton.next(k), Some(polynomial(values.len() as i128 - 1 + k as i128)), "next {}", k);
+                prop_assert_eq!(newton.prev(k), Some(polynomial(-(k as i128))), "prev {}", k);
+            }
         }
+    }
+
+    #[test]
+    fn empty_and_constant_histories() {
+        assert_eq!(History::<i64>::parse("").unwrap().predict(), Some((0, 0)));
+        assert_eq!(History::<i64>::parse("7").unwrap().predict(), Some((7, 7)));
+        assert_eq!(History::<i64>::parse("0 0 0").unwrap().predict(), Some((0, 0)));
+    }
 
-        (prev, next)
+    #[test]
+    fn intermediate_overflow_falls_back_to_big_integers() {
+        let input = "9223372036854775807 9223372036854775806 9223372036854775804";
+        assert_eq!(predict_all::<i64>(input), None);
+        assert_eq!(predict_all::<BigInt>(input), Some((BigInt::from(i64::MAX), BigInt::from(i64::MAX - 6))));
+        assert_eq!(predict_all::<i128>(input), Some((i64::MAX as i128, i64::MAX as i128 - 6)));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn values_beyond_i64_fall_back_to_big_integers() {
+        let input = "9223372036854775807 9223372036854775808";
+        assert_eq!(predict_all::<i64>(input), None);
+        assert_eq!(predict_all::<BigInt>(input), Some((BigInt::from(i64::MAX) - 1, BigInt::from(i64::MAX) + 2)));
+    }
+
+    #[test]
+    fn far_extrapolation_agrees_between_number_types() {
+        let steps = 1_000_000_000_000;
+        let (prev, next) = extrapolate::<i128>(SAMPLE, steps).unwrap();
+        assert_eq!(extrapolate::<BigInt>(SAMPLE, steps), Some((BigInt::from(prev), BigInt::from(next))));
+        assert_eq!(extrapolate::<i64>(SAMPLE, steps), None);
+    }
+}